@@ -28,8 +28,9 @@ const client_secret = process.env.CLIENT_SECRET;
 
 
 const ShazamResult = StructType({
-    name: ref.refType('uint8'),   // pointer to u8
-    artist: ref.refType('uint8')  // pointer to u8
+    name: ref.refType('uint8'),     // pointer to u8
+    artist: ref.refType('uint8'),   // pointer to u8
+    metadata: ref.refType('uint8')  // pointer to u8, JSON of the song info
   });
   
 
@@ -46,7 +47,19 @@ const libPath = path.resolve(__dirname, '../target/release/libshazam.dylib');
               'bool'                 // to_recognize: bool
             ]
         ],
-        'free_rust_strings': ['void', [ref.refType('uint8'), ref.refType('uint8')]] // Takes two *mut u8 pointers
+        'run_shazam_with_metadata': [
+            ShazamResult,
+            [
+              ref.refType('uint8'),  // metadata_json: *const u8
+              'size_t',              // metadata_json_len: usize
+              'bool'                 // to_recognize: bool
+            ]
+        ],
+        'shazam_catalogue_stats': [
+            ShazamResult, // name: error message, metadata: stats JSON
+            ['size_t']    // top_hashes: usize
+        ],
+        'free_rust_strings': ['void', [ref.refType('uint8'), ref.refType('uint8'), ref.refType('uint8')]] // Takes three *mut u8 pointers
     });
 
 const app = express();
@@ -90,6 +103,18 @@ app.get('/songs/count', (req, res) => {
         res.status(200).json({ count: results[0].song_count });
     })
 })
+
+app.get('/stats', (req, res) => {
+    const topHashes = parseInt(req.query.top) || 10;
+    const result = readShazamResult(rustLib.shazam_catalogue_stats(topHashes));
+
+    if (!result.metadata) {
+        console.error('Error fetching stats:', result.name);
+        res.status(500).send('Database error');
+        return;
+    }
+    res.status(200).json(result.metadata);
+})
 async function get_token() {
     const response = await fetch("https://accounts.spotify.com/api/token", {
         method: "POST",
@@ -119,14 +144,33 @@ function runShazam(songName, artistName, toRecognize = true) {
         toRecognize                 // to_recognize
     );
 
+    return readShazamResult(resultStruct);
+}
+
+function runShazamWithMetadata(metadata, toRecognize = false) {
+    console.log("Running Shazam: ", metadata.name, metadata.artist);
+
+    const metadataBuffer = Buffer.from(JSON.stringify(metadata), 'utf8');
+    const resultStruct = rustLib.run_shazam_with_metadata(metadataBuffer, metadataBuffer.length, toRecognize);
+
+    return readShazamResult(resultStruct);
+}
+
+function readShazamResult(resultStruct) {
     const namePtr = resultStruct.name;
   const artistPtr = resultStruct.artist;
+  const metadataPtr = resultStruct.metadata;
     
   const resultName = ref.readCString(namePtr);
   const resultArtist = ref.readCString(artistPtr);
-
-    rustLib.free_rust_strings(namePtr, artistPtr);
-    return { name: resultName, artist: resultArtist };
+  const resultMetadata = ref.readCString(metadataPtr);
+
+    rustLib.free_rust_strings(namePtr, artistPtr, metadataPtr);
+    return {
+        name: resultName,
+        artist: resultArtist,
+        metadata: resultMetadata ? JSON.parse(resultMetadata) : null
+    };
 }
 app.post('/upload-song', async (req, res) => {
 
@@ -152,7 +196,14 @@ app.post('/upload-song', async (req, res) => {
         if (downloadStatus == 500) {
             res.status(500).send("Error while downloading audio");
         }
-        runShazam(data.name, data.artists[0].name, toRecognize);
+        runShazamWithMetadata({
+            name: data.name,
+            artist: data.artists[0].name,
+            album: data.album?.name,
+            duration: data.duration_ms / 1000,
+            isrc: data.external_ids?.isrc,
+            external_ids: { spotify: data.id }
+        }, toRecognize);
 
         return res.status(200).json({uploadStatus : "OK"});
       
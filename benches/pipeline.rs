@@ -0,0 +1,110 @@
+// One benchmark per pipeline stage at realistic sizes: a 3-minute track for fingerprinting and
+// a 10-second query scored against 10k candidate rows. Run with `cargo bench`, or e.g.
+// `cargo bench -- match_song` for a single stage.
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{SAMPLE_RATE, chord_song, excerpt, tone_song, upsample};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use shazam::degradation::SeededRng;
+use shazam::fingerprint::Fingerprint;
+use shazam::pipeline::{
+    HashWeights, MatchConfig, create_pairs, find_spectral_peaks, match_song, process_wav_file, resample_to_output, window_audio,
+};
+use std::collections::{HashMap, HashSet};
+use std::hint::black_box;
+use std::path::PathBuf;
+
+const TRACK_SECONDS: f64 = 180.0;
+const QUERY_SECONDS: f64 = 10.0;
+const CANDIDATE_ROWS: usize = 10_000;
+const CANDIDATE_SONGS: u64 = 200;
+const RECORDING_RATE: f64 = 44_100.0;
+
+// Melody over chords, at the pipeline's 8 kHz
+fn track() -> Vec<f64> {
+    tone_song(TRACK_SECONDS, 1).iter().zip(chord_song(TRACK_SECONDS, 2)).map(|(tone, chord)| 0.6 * tone + 0.4 * chord).collect()
+}
+
+fn write_wav(samples: &[f64], sample_rate: f64) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("shazam-bench-{}.wav", std::process::id()));
+    let spec = hound::WavSpec { channels: 1, sample_rate: sample_rate as u32, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for &sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f64) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn audio_stages(c: &mut Criterion) {
+    let recording = upsample(&track(), RECORDING_RATE);
+    let wav_path = write_wav(&recording, RECORDING_RATE);
+
+    let mut group = c.benchmark_group("audio");
+    group.sample_size(10);
+    group.bench_function("resample_to_output 3 min 44.1 kHz", |b| {
+        b.iter_batched(|| recording.clone(), |samples| resample_to_output(samples, RECORDING_RATE).unwrap(), BatchSize::LargeInput)
+    });
+    group.bench_function("process_wav_file 3 min 44.1 kHz", |b| {
+        b.iter(|| process_wav_file(wav_path.to_str().unwrap()).unwrap())
+    });
+    group.finish();
+
+    std::fs::remove_file(wav_path).unwrap();
+}
+
+fn fingerprint_stages(c: &mut Criterion) {
+    let audio = vec![track()];
+    let spectrogram = window_audio(audio.clone()).unwrap();
+    let peaks = find_spectral_peaks(&spectrogram).unwrap();
+
+    let mut group = c.benchmark_group("fingerprint");
+    group.sample_size(10);
+    group.bench_function("window_audio 3 min", |b| {
+        b.iter_batched(|| audio.clone(), |audio| window_audio(audio).unwrap(), BatchSize::LargeInput)
+    });
+    group.bench_function("find_spectral_peaks 3 min", |b| b.iter(|| find_spectral_peaks(black_box(&spectrogram)).unwrap()));
+    group.bench_function("create_pairs 3 min", |b| b.iter(|| create_pairs(black_box(&peaks)).unwrap()));
+    group.finish();
+}
+
+// Rows as a store returns them for the query: grouped by song, each carrying one of the
+// query's hashes. One song holds the query at a fixed offset, the rest are chance hits.
+fn candidate_rows(query: &Fingerprint) -> (Vec<(u64, u64, f64)>, HashWeights) {
+    let mut rng = SeededRng::new(3);
+    let offset = 42.0;
+    let mut rows: Vec<(u64, u64, f64)> = query.hashes.iter().zip(&query.anchor_times)
+        .map(|(&hash, &time)| (hash, 1, time + offset))
+        .collect();
+    while rows.len() < CANDIDATE_ROWS {
+        let hash = query.hashes[(rng.next_u64() % query.hashes.len() as u64) as usize];
+        rows.push((hash, 2 + rng.next_u64() % CANDIDATE_SONGS, rng.range(0.0, TRACK_SECONDS)));
+    }
+    rows.sort_by_key(|row| row.1);
+
+    let mut song_counts: HashMap<u64, u64> = HashMap::new();
+    let mut seen = HashSet::new();
+    for &(hash, song_id, _) in &rows {
+        if seen.insert((hash, song_id)) {
+            *song_counts.entry(hash).or_default() += 1;
+        }
+    }
+    (rows, HashWeights::new(CANDIDATE_SONGS + 1, song_counts))
+}
+
+fn match_stage(c: &mut Criterion) {
+    let query = Fingerprint::from_samples(&excerpt(&track(), 42.0, QUERY_SECONDS), SAMPLE_RATE).unwrap();
+    let (rows, weights) = candidate_rows(&query);
+
+    c.bench_function("match_song 10 s query, 10k rows", |b| {
+        b.iter_batched(
+            || rows.clone(),
+            |rows| match_song(rows, &query.hashes, &query.anchor_times, query.duration, Some(&weights), Some(MatchConfig::default())).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, audio_stages, fingerprint_stages, match_stage);
+criterion_main!(benches);
@@ -11,16 +11,19 @@ use std::io::{Write, BufWriter};
 use crate::constants::OUTPUT_SAMPLE_RATE;
 
 pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
-    // Ensure output directory exists
+    // Files shared with the Node server live in the audio directory
+    process_wav_file(&format!("../audio/{}", audio_path))
+}
 
+// Same as `process_audio` for a WAV file at any path
+pub fn process_wav_file(full_open_path: &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
     // Open the input WAV file
-    let full_open_path = format!("../audio/{}", audio_path);
     let mut reader = WavReader::open(full_open_path)?;
     let spec = reader.spec();
     let input_sample_rate = spec.sample_rate;
     let channels = spec.channels as usize;
 
-    println!("Input file: {} channels, {} Hz", channels, input_sample_rate);
+    log::debug!("Input file: {} channels, {} Hz", channels, input_sample_rate);
 
     // Read all samples from the file
     let samples: Vec<i16> = reader.samples::<i16>().filter_map(Result::ok).collect();
@@ -29,7 +32,7 @@ pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>>
         return Err("Input file contains no samples".into());
     }
     
-    println!("Read {} samples from input file", samples.len());
+    log::debug!("Read {} samples from input file", samples.len());
     
     // Organize samples by channel
     let mut waves_in: Vec<Vec<f64>> = vec![Vec::new(); channels];
@@ -39,8 +42,8 @@ pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>>
         waves_in[0] = samples.iter().map(|&s| s as f64 / 32768.0).collect();
     } else {
         // Multi-channel input - separate channels
-        for c in 0..channels {
-            waves_in[c] = samples
+        for (c, wave) in waves_in.iter_mut().enumerate() {
+            *wave = samples
                 .iter()
                 .skip(c)
                 .step_by(channels)
@@ -64,7 +67,17 @@ pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>>
 
 
     
-    println!("Mono input length: {}", mono_input.len());
+    log::debug!("Mono input length: {}", mono_input.len());
+    resample_to_output(mono_input, input_sample_rate as f64)
+}
+
+// Resamples a mono buffer to OUTPUT_SAMPLE_RATE. Returns the same one-channel layout as
+// `process_audio`, so decoded audio from any source can go through the rest of the pipeline.
+pub fn resample_to_output(mono_input: Vec<f64>, input_sample_rate: f64) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    if mono_input.is_empty() {
+        return Err("Input contains no samples".into());
+    }
+
     // Set up resampler
     let params = SincInterpolationParameters {
         sinc_len: 256,
@@ -76,9 +89,9 @@ pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>>
     
     // Calculate output length (approximate)
     let output_sample_rate = OUTPUT_SAMPLE_RATE;
-    let ratio = output_sample_rate / input_sample_rate as f64;
+    let ratio = output_sample_rate / input_sample_rate;
     let expected_output_len = (mono_input.len() as f64 * ratio).ceil() as usize;
-    println!("Expected output length: {} samples", expected_output_len);
+    log::debug!("Expected output length: {} samples", expected_output_len);
     
     // Create resampler with correct ratio (output_rate / input_rate)
     let mut resampler = SincFixedIn::<f64>::new(
@@ -98,7 +111,7 @@ pub fn process_audio(audio_path : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>>
 
 
     
-    println!("Resampled output length: {}", waves_out[0].len());
+    log::debug!("Resampled output length: {}", waves_out[0].len());
 
     // let file = File::create("./log/audio_sample.txt")?;
     // let mut writer = BufWriter::new(file);
@@ -0,0 +1,315 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use crate::engine::ShazamEngine;
+use crate::fingerprint_store::{is_transient_error, FingerprintStore, SongMetadata};
+
+const DEFAULT_ARTIST: &str = "Unknown artist";
+// Tries per file when the store reports a deadlock or a busy database, with a growing pause
+const MAX_INGEST_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// One file to ingest and the song it holds
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub metadata: SongMetadata,
+}
+
+// CSV manifests have a header row with `path`, `title` (or `name`) and `artist`, and
+// optionally `album`, `duration`, `isrc`, `tags` and `external_ids` (a JSON object)
+#[derive(Deserialize)]
+struct CsvRow {
+    path: PathBuf,
+    #[serde(alias = "name")]
+    title: String,
+    artist: String,
+    #[serde(default)]
+    album: Option<String>,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    isrc: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    external_ids: Option<String>,
+}
+
+impl CsvRow {
+    fn into_entry(self) -> Result<ManifestEntry, Box<dyn std::error::Error>> {
+        let external_ids = match self.external_ids.filter(|json| !json.trim().is_empty()) {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        };
+        let metadata = SongMetadata {
+            name: self.title,
+            artist: self.artist,
+            album: self.album.filter(|album| !album.is_empty()),
+            duration: self.duration,
+            isrc: self.isrc.filter(|isrc| !isrc.is_empty()),
+            external_ids,
+            tags: self.tags.filter(|tags| !tags.is_empty()),
+            content_sha256: None,
+        };
+        Ok(ManifestEntry { path: self.path, metadata })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FileStatus {
+    Ingested { song_id: u64, hashes: usize },
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub name: String,
+    pub artist: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(flatten)]
+    pub status: FileStatus,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BatchReport {
+    pub ingested: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub files: Vec<FileReport>,
+}
+
+impl BatchReport {
+    fn from_files(files: Vec<FileReport>) -> Self {
+        let mut report = Self::default();
+        for file in &files {
+            match file.status {
+                FileStatus::Ingested { .. } => report.ingested += 1,
+                FileStatus::Skipped { .. } => report.skipped += 1,
+                FileStatus::Failed { .. } => report.failed += 1,
+            }
+        }
+        report.files = files;
+        report
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+// Called once per file as it finishes: (files done, total files, report for the file)
+pub type ProgressCallback<'a> = dyn Fn(usize, usize, &FileReport) + Sync + 'a;
+
+fn is_wav(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("wav"))
+}
+
+// Metadata from a file name: "Artist - Title.wav", or just the title with `default_artist`
+fn metadata_from_file_name(path: &Path, default_artist: &str) -> SongMetadata {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match stem.split_once(" - ") {
+        Some((artist, title)) => SongMetadata::new(title.trim(), artist.trim()),
+        None => SongMetadata::new(stem.trim(), default_artist),
+    }
+}
+
+fn walk_directory(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            walk_directory(&entry, files)?;
+        } else if is_wav(&entry) {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// Expands directories into the WAV files they contain, recursively and in name order; files
+// named directly are kept whatever their extension
+pub fn collect_wav_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk_directory(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+// Every WAV file under `directory`, recursively, named "Artist - Title.wav" or "Title.wav"
+pub fn scan_directory(directory: &Path, default_artist: Option<&str>) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    walk_directory(directory, &mut files)?;
+
+    let default_artist = default_artist.unwrap_or(DEFAULT_ARTIST);
+    Ok(files.into_iter()
+        .map(|path| ManifestEntry { metadata: metadata_from_file_name(&path, default_artist), path })
+        .collect())
+}
+
+// Reads a `.csv` manifest or a `.json` manifest (an array of objects with `path` plus
+// `SongMetadata` fields). Relative paths are resolved against the manifest's directory.
+pub fn read_manifest(manifest_path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let extension = manifest_path.extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    let mut entries: Vec<ManifestEntry> = match extension.as_str() {
+        "csv" => {
+            let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(manifest_path)?;
+            let mut entries = Vec::new();
+            for (row_number, row) in reader.deserialize::<CsvRow>().enumerate() {
+                let row = row.map_err(|e| format!("Invalid manifest row {}: {}", row_number + 2, e))?;
+                entries.push(row.into_entry().map_err(|e| format!("Invalid manifest row {}: {}", row_number + 2, e))?);
+            }
+            entries
+        }
+        "json" => serde_json::from_reader(BufReader::new(File::open(manifest_path)?))
+            .map_err(|e| format!("Invalid manifest {}: {}", manifest_path.display(), e))?,
+        _ => return Err(format!("Manifest must be a .csv or .json file: {}", manifest_path.display()).into()),
+    };
+
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    for entry in entries.iter_mut() {
+        if entry.path.is_relative() {
+            entry.path = base.join(&entry.path);
+        }
+    }
+    Ok(entries)
+}
+
+pub fn content_hash(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn indexed_content_hashes(store: &dyn FingerprintStore) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+    Ok(store.list_songs()?
+        .into_iter()
+        .filter_map(|song| song.metadata.content_sha256)
+        .collect())
+}
+
+fn report(entry: &ManifestEntry, content_hash: Option<String>, status: FileStatus) -> FileReport {
+    FileReport {
+        path: entry.path.clone(),
+        name: entry.metadata.name.clone(),
+        artist: entry.metadata.artist.clone(),
+        content_hash,
+        status,
+    }
+}
+
+fn ingest_entry(engine: &ShazamEngine, entry: &ManifestEntry, content_hash: &str) -> Result<(u64, usize), Box<dyn std::error::Error>> {
+    let path = entry.path.to_str().ok_or("Path is not valid UTF-8")?;
+    let fingerprint = engine.fingerprint_wav_file(path)?;
+    let metadata = SongMetadata { content_sha256: Some(content_hash.to_string()), ..entry.metadata.clone() };
+
+    // Parallel ingests into one database can deadlock or find it busy; the store rolls the
+    // losing transaction back, so it is simply tried again after a short pause
+    let mut attempt = 1;
+    loop {
+        match engine.ingest_fingerprint(&metadata, &fingerprint) {
+            Ok(song_id) => return Ok((song_id, fingerprint.hashes.len())),
+            Err(e) if attempt < MAX_INGEST_ATTEMPTS && is_transient_error(e.as_ref()) => {
+                log::warn!("Retrying {} after a transient error: {}", path, e);
+                std::thread::sleep(RETRY_DELAY * attempt);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Fingerprints and stores every entry on the current rayon thread pool. Files whose content
+// is already in the catalogue are skipped. Files sharing content within the batch are tried in
+// order until one is stored, and the rest are then skipped; a failing file is reported and
+// does not stop the rest.
+pub fn ingest_batch(engine: &ShazamEngine, entries: &[ManifestEntry], on_progress: &ProgressCallback) -> Result<BatchReport, Box<dyn std::error::Error>> {
+    let total = entries.len();
+    let done = AtomicUsize::new(0);
+    let finish = |file: FileReport| {
+        on_progress(done.fetch_add(1, Ordering::SeqCst) + 1, total, &file);
+        file
+    };
+
+    let hashes: Vec<Result<String, String>> = entries.par_iter()
+        .map(|entry| content_hash(&entry.path).map_err(|e| e.to_string()))
+        .collect();
+
+    // Skip against the catalogue up front, and group the rest by content in batch order
+    let indexed = indexed_content_hashes(engine.store())?;
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    let mut files: Vec<Option<FileReport>> = vec![None; total];
+    for (index, (entry, hash)) in entries.iter().zip(hashes).enumerate() {
+        match hash {
+            Err(error) => files[index] = Some(finish(report(entry, None, FileStatus::Failed { error }))),
+            Ok(hash) if indexed.contains(&hash) => {
+                let reason = "same audio content is already indexed".to_string();
+                files[index] = Some(finish(report(entry, Some(hash), FileStatus::Skipped { reason })));
+            }
+            Ok(hash) => {
+                let group = *group_of.entry(hash.clone()).or_insert_with(|| {
+                    groups.push((hash, Vec::new()));
+                    groups.len() - 1
+                });
+                groups[group].1.push(index);
+            }
+        }
+    }
+
+    let ingested: Vec<(usize, FileReport)> = groups.into_par_iter()
+        .flat_map_iter(|(hash, members)| {
+            let mut reports = Vec::with_capacity(members.len());
+            let mut stored_from: Option<&Path> = None;
+            for index in members {
+                let entry = &entries[index];
+                let status = match stored_from {
+                    Some(path) => FileStatus::Skipped { reason: format!("same audio content as {}", path.display()) },
+                    None => match ingest_entry(engine, entry, &hash) {
+                        Ok((song_id, hashes)) => {
+                            stored_from = Some(&entry.path);
+                            FileStatus::Ingested { song_id, hashes }
+                        }
+                        Err(e) => FileStatus::Failed { error: e.to_string() },
+                    },
+                };
+                reports.push((index, finish(report(entry, Some(hash.clone()), status))));
+            }
+            reports
+        })
+        .collect();
+    for (index, file) in ingested {
+        files[index] = Some(file);
+    }
+
+    Ok(BatchReport::from_files(files.into_iter().flatten().collect()))
+}
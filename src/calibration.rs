@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+// Turns the statistics of a match into the probability that it is right, with a logistic
+// model over two features of the winning offset peak:
+// - hash fraction: weighted hashes at the peak per hash of the clip, the share of the clip
+//   that agrees on the alignment
+// - margin: how much stronger the peak is than the runner-up, the best peak of any other
+//   song (see `MatchCandidate::margin`)
+// p = 1 / (1 + exp(-(intercept + hash_fraction_weight * ln(hash fraction) + margin_weight * ln(margin))))
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub intercept: f64,
+    pub hash_fraction_weight: f64,
+    pub margin_weight: f64,
+}
+
+// Fitted with `Calibration::fit`, line fit on (`MatchConfig::with_line_fit`), on an
+// evaluation of synthesized songs: 3-10 s clips, clean and degraded (noise down to 0 dB SNR,
+// phone speaker, reverb, clipping, codec bandlimiting), a third of them 10-30 s long and
+// played up to 1.5% off speed, half of them from songs outside the catalogue. Refit on your
+// own catalogue with `shazam evaluate --fit-calibration`.
+impl Default for Calibration {
+    fn default() -> Self {
+        Self { intercept: -1.46, hash_fraction_weight: 0.46, margin_weight: 7.64 }
+    }
+}
+
+// One recognized clip for fitting: the top candidate's features and whether it was right
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationSample {
+    pub hash_fraction: f64,
+    pub margin: f64,
+    pub correct: bool,
+}
+
+// Penalty on the two feature weights, keeping the fit finite when the data is separable
+const RIDGE: f64 = 1.0;
+const MAX_ITERATIONS: usize = 100;
+
+fn features(hash_fraction: f64, margin: f64) -> [f64; 3] {
+    [1.0, hash_fraction.max(f64::MIN_POSITIVE).ln(), margin.max(f64::MIN_POSITIVE).ln()]
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// Solves the 3x3 system `a * x = b` by Gaussian elimination with partial pivoting
+fn solve(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for column in 0..3 {
+        let pivot = (column..3).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+        if a[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(column, pivot);
+        b.swap(column, pivot);
+        let pivot_row = a[column];
+        for row in column + 1..3 {
+            let factor = a[row][column] / pivot_row[column];
+            for (value, pivot_value) in a[row][column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[column];
+        }
+    }
+
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let known: f64 = (row + 1..3).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - known) / a[row][row];
+    }
+    Some(x)
+}
+
+impl Calibration {
+    // Probability (0 to 1) that a candidate with these statistics is the right song
+    pub fn probability(&self, hash_fraction: f64, margin: f64) -> f64 {
+        let [_, fraction, margin] = features(hash_fraction, margin);
+        sigmoid(self.intercept + self.hash_fraction_weight * fraction + self.margin_weight * margin)
+    }
+
+    // Reads a calibration written by `write_json`
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|e| format!("Invalid calibration {}: {}", path, e).into())
+    }
+
+    // The calibration at SHAZAM_CALIBRATION, if set
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match env::var("SHAZAM_CALIBRATION") {
+            Ok(path) => Ok(Some(Self::from_file(&path)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    // Ridge-penalised logistic regression by Newton's method. Needs both right and wrong
+    // answers among the samples.
+    pub fn fit(samples: &[CalibrationSample]) -> Result<Self, Box<dyn std::error::Error>> {
+        let correct = samples.iter().filter(|sample| sample.correct).count();
+        if correct == 0 || correct == samples.len() {
+            return Err("Fitting a calibration needs both correct and incorrect answers".into());
+        }
+
+        let rows: Vec<([f64; 3], f64)> = samples.iter()
+            .map(|sample| (features(sample.hash_fraction, sample.margin), if sample.correct { 1.0 } else { 0.0 }))
+            .collect();
+        let mut weights = [0.0; 3];
+
+        for _ in 0..MAX_ITERATIONS {
+            // Gradient and Hessian of the penalised negative log-likelihood
+            let mut gradient = [0.0, RIDGE * weights[1], RIDGE * weights[2]];
+            let mut hessian = [[0.0, 0.0, 0.0], [0.0, RIDGE, 0.0], [0.0, 0.0, RIDGE]];
+            for (x, y) in &rows {
+                let p = sigmoid(x.iter().zip(&weights).map(|(x, w)| x * w).sum());
+                for i in 0..3 {
+                    gradient[i] += (p - y) * x[i];
+                    for j in 0..3 {
+                        hessian[i][j] += p * (1.0 - p) * x[i] * x[j];
+                    }
+                }
+            }
+
+            let step = solve(hessian, gradient).ok_or("Calibration fit did not converge")?;
+            for i in 0..3 {
+                weights[i] -= step[i];
+            }
+            if step.iter().all(|step| step.abs() < 1e-9) {
+                break;
+            }
+        }
+
+        Ok(Self { intercept: weights[0], hash_fraction_weight: weights[1], margin_weight: weights[2] })
+    }
+}
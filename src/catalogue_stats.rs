@@ -0,0 +1,62 @@
+use serde::Serialize;
+use crate::fingerprint::FingerprintScheme;
+use crate::fingerprint_store::{FingerprintStore, HashFrequency};
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Distribution {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl Distribution {
+    pub fn from_values(mut values: Vec<u64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_unstable();
+
+        // Nearest-rank percentile
+        let percentile = |p: f64| values[((p / 100.0 * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+        Self {
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<u64>() as f64 / values.len() as f64,
+            median: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CatalogueStats {
+    pub song_count: u64,
+    pub fingerprint_count: u64,
+    pub fingerprints_per_song: Distribution,
+    // Hashes found in the most songs: candidates for a stop-list
+    pub most_frequent_hashes: Vec<HashFrequency>,
+    // Bytes used on disk, when the backend can tell
+    pub index_size_bytes: Option<u64>,
+    // Scheme the stored fingerprints were made with; None for a store that has none yet
+    pub scheme_version: Option<u32>,
+    pub hashing_scheme: Option<FingerprintScheme>,
+}
+
+pub fn catalogue_stats(store: &dyn FingerprintStore, top_hashes: usize) -> Result<CatalogueStats, Box<dyn std::error::Error>> {
+    let counts: Vec<u64> = store.fingerprint_counts()?.into_iter().map(|(_, count)| count).collect();
+    let scheme = store.scheme_parameters()?;
+
+    Ok(CatalogueStats {
+        song_count: counts.len() as u64,
+        fingerprint_count: counts.iter().sum(),
+        fingerprints_per_song: Distribution::from_values(counts),
+        most_frequent_hashes: store.most_frequent_hashes(top_hashes)?,
+        index_size_bytes: store.storage_size()?,
+        scheme_version: scheme.as_ref().map(|scheme| scheme.version),
+        hashing_scheme: scheme.map(|scheme| scheme.hashing),
+    })
+}
@@ -0,0 +1,151 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use crate::fingerprint::FingerprintScheme;
+use crate::fingerprint_store::{FingerprintStore, SongMetadata};
+
+pub use crate::fingerprint::SchemeParameters;
+
+// Portable catalogue dump in JSON Lines: a header line followed by one line per song.
+// Files whose name ends in `.gz` are gzip-compressed.
+const EXPORT_FORMAT: &str = "shazam-fingerprints";
+// Version 2 added the optional song metadata fields and version 3 the hashing scheme; older
+// files still import, as pairs
+const EXPORT_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct ExportHeader {
+    format: String,
+    version: u32,
+    scheme: SchemeParameters,
+    song_count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedSong {
+    id: u64,
+    #[serde(flatten)]
+    metadata: SongMetadata,
+    hashes: Vec<u64>,
+    anchor_times: Vec<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    // Songs already present in the target store (see `SongMetadata::same_recording`)
+    pub skipped: usize,
+    // Song id in the export file -> song id assigned by the target store
+    pub id_map: BTreeMap<u64, u64>,
+}
+
+fn open_reader(path: &str) -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = if path.ends_with(".gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+fn write_catalogue<W: Write>(store: &dyn FingerprintStore, writer: &mut W) -> Result<usize, Box<dyn std::error::Error>> {
+    let header = ExportHeader {
+        format: EXPORT_FORMAT.to_string(),
+        version: EXPORT_VERSION,
+        scheme: store.scheme_parameters()?.unwrap_or_else(|| SchemeParameters::current(FingerprintScheme::default())),
+        song_count: usize::try_from(store.song_count()?)?,
+    };
+    serde_json::to_writer(&mut *writer, &header)?;
+    writeln!(writer)?;
+
+    let mut exported = 0;
+    for song in store.all_songs_hashes()? {
+        let (song, hashes, anchor_times) = song?;
+        let song = ExportedSong { id: song.id, metadata: song.metadata, hashes, anchor_times };
+        serde_json::to_writer(&mut *writer, &song)?;
+        writeln!(writer)?;
+        exported += 1;
+    }
+
+    Ok(exported)
+}
+
+// Writes every song in the store with its fingerprints; returns the number of songs exported
+pub fn export_catalogue(store: &dyn FingerprintStore, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    let exported = if path.ends_with(".gz") {
+        let mut encoder = GzEncoder::new(&mut file, Compression::default());
+        let exported = write_catalogue(store, &mut encoder)?;
+        encoder.finish()?;
+        exported
+    } else {
+        write_catalogue(store, &mut file)?
+    };
+
+    file.flush()?;
+    Ok(exported)
+}
+
+// Merges an export file into the store. Every song gets a fresh id from the target store;
+// songs already present there are skipped. The export's fingerprint scheme must be the
+// store's, and becomes it for a store that has none yet.
+pub fn import_catalogue(store: &dyn FingerprintStore, path: &str) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    let mut lines = open_reader(path)?.lines();
+
+    let header_line = lines.next().ok_or("Export file is empty")??;
+    let header: ExportHeader = serde_json::from_str(&header_line)
+        .map_err(|e| format!("Invalid export header: {}", e))?;
+    if header.format != EXPORT_FORMAT {
+        return Err(format!("Unknown export format '{}'", header.format).into());
+    }
+    if header.version > EXPORT_VERSION {
+        return Err(format!("Export file version {} is newer than supported version {}", header.version, EXPORT_VERSION).into());
+    }
+    SchemeParameters::current(header.scheme.hashing).check_compatible(&header.scheme)
+        .map_err(|e| format!("Export cannot be used by this build: {}", e))?;
+
+    // Parse everything up front so a truncated or corrupt file imports nothing
+    let mut songs: Vec<ExportedSong> = Vec::with_capacity(header.song_count);
+    for (line_number, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let song: ExportedSong = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid song record on line {}: {}", line_number + 2, e))?;
+        songs.push(song);
+    }
+    if songs.len() != header.song_count {
+        return Err(format!("Export file is incomplete: header lists {} songs, found {}", header.song_count, songs.len()).into());
+    }
+
+    store.claim_scheme(&header.scheme)
+        .map_err(|e| format!("Export does not match the catalogue: {}", e))?;
+
+    let mut existing: Vec<SongMetadata> = store.list_songs()?
+        .into_iter()
+        .map(|song| song.metadata)
+        .collect();
+
+    let mut report = ImportReport::default();
+    for song in songs {
+        if existing.iter().any(|metadata| metadata.same_recording(&song.metadata)) {
+            log::info!("Skipping '{}' by '{}': already in the database", song.metadata.name, song.metadata.artist);
+            report.skipped += 1;
+            continue;
+        }
+
+        let new_id = store.ingest_song(&song.metadata, &song.hashes, &song.anchor_times)?;
+        existing.push(song.metadata);
+        report.id_map.insert(song.id, new_id);
+        report.imported += 1;
+    }
+
+    Ok(report)
+}
@@ -1,3 +1,20 @@
 pub const OUTPUT_SAMPLE_RATE : f64 = 8000.0;
 pub const FRAME_LENGTH : usize = 1024;
-pub const HOP_LENGTH : usize = FRAME_LENGTH/2;
\ No newline at end of file
+pub const HOP_LENGTH : usize = FRAME_LENGTH/2;
+// Frames after the anchor frame searched for target peaks
+pub const TARGET_ZONE_FRAMES : usize = 6;
+pub const MAX_TARGETS_PER_ANCHOR : usize = 5;
+// Bump whenever a change to the spectrogram, peak picking or hashing alters the hashes
+// produced for the same audio; stored indexes built with another version are incompatible.
+// tests/golden_fingerprints.rs fails until the goldens for the new version are recorded.
+pub const FINGERPRINT_SCHEME_VERSION : u32 = 2;
+// Triplet scheme (`FingerprintScheme::Triplets`): a wider target zone than pairs, so the
+// time ratios between peaks take more distinct values
+pub const TRIPLET_ZONE_FRAMES : usize = 8;
+pub const TRIPLET_TARGETS_PER_ANCHOR : usize = 8;
+pub const MAX_TRIPLETS_PER_ANCHOR : usize = 10;
+pub const TRIPLET_FREQUENCY_RATIO_STEPS : f64 = 8.0;   // per octave
+pub const TRIPLET_TIME_RATIO_STEPS : f64 = 8.0;
+// Tempo factors tried when matching triplet fingerprints: 0.9x to 1.1x in 0.5% steps
+pub const MAX_TEMPO_CHANGE : f64 = 0.1;
+pub const TEMPO_STEP : f64 = 0.005;
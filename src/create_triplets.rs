@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use crate::constants::*;
+
+// Log2 of the frequency ratio of two peak bins, quantized to 1/FREQUENCY_RATIO_STEPS octave
+fn quantize_frequency_ratio(bin: usize, anchor_bin: usize) -> i64 {
+    ((bin.max(1) as f64 / anchor_bin.max(1) as f64).log2() * TRIPLET_FREQUENCY_RATIO_STEPS).round() as i64
+}
+
+// Ratio of the two frame deltas (between 0 and 1), quantized to 1/TIME_RATIO_STEPS
+fn quantize_time_ratio(near_frames: usize, far_frames: usize) -> i64 {
+    (near_frames as f64 / far_frames as f64 * TRIPLET_TIME_RATIO_STEPS).round() as i64
+}
+
+// Only ratios go into the hash: playing the audio faster scales every frequency and every
+// time delta by the same factor, which cancels out. The quantized ratios are packed into the
+// key as 16-bit two's complement fields, so keys stay the same across builds.
+fn hash_function(anchor_bin: usize, near: (usize, usize), far: (usize, usize)) -> u64 {
+    let field = |value: i64| value as u16 as u64;
+    field(quantize_frequency_ratio(near.1, anchor_bin)) << 32
+        | field(quantize_frequency_ratio(far.1, anchor_bin)) << 16
+        | field(quantize_time_ratio(near.0, far.0))
+}
+
+// Tempo- and pitch-tolerant alternative to `create_pairs`: every peak anchors triplets with
+// two later peaks from its target zone, a near one and a far one in a later frame. A held
+// chord yields the same triplet frame after frame, so a run of one hash in consecutive
+// frames is kept only at its first frame. Returns the hash keys together with the anchor
+// time of each triplet.
+pub fn create_triplets(peaks: &[Vec<usize>]) -> Result<(Vec<u64>, Vec<f64>), Box<dyn std::error::Error>> {
+    let mut keys: Vec<u64> = Vec::new();
+    let mut values: Vec<f64> = Vec::new();
+    // Last frame each hash was seen in
+    let mut last_seen: HashMap<u64, usize> = HashMap::new();
+
+    for (i, anchors) in peaks.iter().enumerate() {
+        // (frames after the anchor, bin) of the nearest peaks in the target zone
+        let targets: Vec<(usize, usize)> = (i + 1..peaks.len().min(i + 1 + TRIPLET_ZONE_FRAMES))
+            .flat_map(|target_i| peaks[target_i].iter().map(move |&bin| (target_i - i, bin)))
+            .take(TRIPLET_TARGETS_PER_ANCHOR)
+            .collect();
+
+        for &anchor_bin in anchors {
+            let triplets = targets.iter().enumerate()
+                .flat_map(|(n, &near)| targets[n + 1..].iter().filter(move |far| far.0 > near.0).map(move |&far| (near, far)))
+                .take(MAX_TRIPLETS_PER_ANCHOR);
+
+            for (near, far) in triplets {
+                let hash_key = hash_function(anchor_bin, near, far);
+                let continues_run = last_seen.insert(hash_key, i).is_some_and(|frame| frame + 1 >= i);
+                if !continues_run {
+                    keys.push(hash_key);
+                    // One division, like `create_pairs`, so the time is the nearest double to
+                    // the exact frame start and survives a JSON round trip
+                    values.push((i * HOP_LENGTH) as f64 / OUTPUT_SAMPLE_RATE);
+                }
+            }
+        }
+    }
+
+    Ok((keys, values))
+}
@@ -0,0 +1,14 @@
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Dumps of intermediate pipeline stages (spectrogram, peaks, hashes) for debugging. They are
+// only written when SHAZAM_DEBUG_LOG_DIR names a directory, e.g. `../log`; otherwise
+// fingerprinting never touches the filesystem.
+pub fn create_log(file_name: &str) -> Result<Option<BufWriter<File>>, Box<dyn std::error::Error>> {
+    match env::var("SHAZAM_DEBUG_LOG_DIR") {
+        Ok(dir) => Ok(Some(BufWriter::new(File::create(Path::new(&dir).join(file_name))?))),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,538 @@
+use rubato::{Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction};
+use rustfft::{FftPlanner, num_complex::Complex};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Controlled damage for query clips, used to test how recognition holds up. Works on mono
+// buffers like the one `process_audio` returns (samples in [-1, 1]); every random choice
+// comes from the seed, so a given seed always produces the same clip.
+
+// Deterministic pseudo-random numbers (SplitMix64)
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+
+    // Standard normal (Box-Muller)
+    pub fn gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Degradation {
+    // Random excerpt of the given length
+    Excerpt { seconds: f64 },
+    // Additive noise at a signal-to-noise ratio in dB
+    WhiteNoise { snr_db: f64 },
+    PinkNoise { snr_db: f64 },
+    // Several synthetic voices talking over the audio, like a crowded room
+    Babble { snr_db: f64, voices: usize },
+    // 4th-order Butterworth filters
+    LowPass { cutoff_hz: f64 },
+    HighPass { cutoff_hz: f64 },
+    // Amplifies by `drive_db` and hard-clips at full scale
+    Clip { drive_db: f64 },
+    // Convolution with a synthetic room response decaying by 60 dB over `rt60` seconds;
+    // `wet` is the reverberant share of the output, 0 to 1
+    Reverb { rt60: f64, wet: f64 },
+    // Lossy-codec imitation: drops everything above `cutoff_hz` and quantizes each frame's
+    // spectrum to `bits` bits, which punches holes in the quieter bins
+    Bandlimit { cutoff_hz: f64, bits: u32 },
+    Gain { db: f64 },
+    // Plays the audio `factor` times as fast, raising its pitch by the same factor, like a
+    // sped-up video clip or a turntable at the wrong speed
+    Speed { factor: f64 },
+}
+
+impl Degradation {
+    // Small phone speaker: no bass, nothing above narrow-band telephony
+    pub fn phone_speaker() -> Vec<Degradation> {
+        vec![Degradation::HighPass { cutoff_hz: 300.0 }, Degradation::LowPass { cutoff_hz: 3400.0 }]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DegradedClip {
+    pub samples: Vec<f64>,
+    // Where the clip starts in the source audio, in seconds
+    pub start: f64,
+}
+
+// Applies `steps` in order. An `Excerpt` step anywhere in the chain moves `start`.
+pub fn degrade(samples: &[f64], sample_rate: f64, steps: &[Degradation], seed: u64) -> Result<DegradedClip, Box<dyn std::error::Error>> {
+    let mut rng = SeededRng::new(seed);
+    let mut clip = DegradedClip { samples: samples.to_vec(), start: 0.0 };
+    // Source seconds per second of the clip so far, changed by `Speed` steps
+    let mut time_scale = 1.0;
+
+    for step in steps {
+        match *step {
+            Degradation::Excerpt { seconds } => {
+                let (excerpt, start) = random_excerpt(&clip.samples, sample_rate, seconds, &mut rng)?;
+                clip.samples = excerpt;
+                clip.start += start * time_scale;
+            }
+            Degradation::WhiteNoise { snr_db } => {
+                let noise: Vec<f64> = (0..clip.samples.len()).map(|_| rng.gaussian()).collect();
+                mix_at_snr(&mut clip.samples, &noise, snr_db);
+            }
+            Degradation::PinkNoise { snr_db } => {
+                let noise = pink_noise(clip.samples.len(), &mut rng);
+                mix_at_snr(&mut clip.samples, &noise, snr_db);
+            }
+            Degradation::Babble { snr_db, voices } => {
+                let noise = babble(clip.samples.len(), sample_rate, voices, &mut rng);
+                mix_at_snr(&mut clip.samples, &noise, snr_db);
+            }
+            Degradation::LowPass { cutoff_hz } => {
+                clip.samples = butterworth(&clip.samples, sample_rate, cutoff_hz, Biquad::low_pass)?;
+            }
+            Degradation::HighPass { cutoff_hz } => {
+                clip.samples = butterworth(&clip.samples, sample_rate, cutoff_hz, Biquad::high_pass)?;
+            }
+            Degradation::Clip { drive_db } => {
+                let gain = db_to_gain(drive_db);
+                clip.samples.iter_mut().for_each(|sample| *sample = (*sample * gain).clamp(-1.0, 1.0));
+            }
+            Degradation::Reverb { rt60, wet } => {
+                clip.samples = reverb(&clip.samples, sample_rate, rt60, wet, &mut rng)?;
+            }
+            Degradation::Bandlimit { cutoff_hz, bits } => {
+                clip.samples = bandlimit(&clip.samples, sample_rate, cutoff_hz, bits)?;
+            }
+            Degradation::Gain { db } => {
+                let gain = db_to_gain(db);
+                clip.samples.iter_mut().for_each(|sample| *sample *= gain);
+            }
+            Degradation::Speed { factor } => {
+                clip.samples = change_speed(&clip.samples, factor)?;
+                time_scale *= factor;
+            }
+        }
+    }
+
+    Ok(clip)
+}
+
+// Plays the samples `factor` times as fast: a band-limited resampling by 1 / factor, so the
+// sample rate is unchanged and every frequency scales by `factor`
+pub fn change_speed(samples: &[f64], factor: f64) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    if factor.is_nan() || factor <= 0.0 {
+        return Err(format!("Speed factor must be positive, got {}", factor).into());
+    }
+    if samples.is_empty() {
+        return Ok(Vec::new());
+    }
+    let params = SincInterpolationParameters {
+        sinc_len: 256,
+        f_cutoff: 0.95,
+        interpolation: SincInterpolationType::Linear,
+        oversampling_factor: 256,
+        window: WindowFunction::BlackmanHarris2,
+    };
+    let mut resampler = SincFixedIn::<f64>::new(1.0 / factor, 1.0, params, samples.len(), 1)?;
+    Ok(resampler.process(&[samples.to_vec()], None)?.remove(0))
+}
+
+fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+fn power(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().map(|sample| sample * sample).sum::<f64>() / samples.len() as f64
+}
+
+// Returns the excerpt and its start time in seconds
+pub fn random_excerpt(samples: &[f64], sample_rate: f64, seconds: f64, rng: &mut SeededRng) -> Result<(Vec<f64>, f64), Box<dyn std::error::Error>> {
+    let length = (seconds * sample_rate).round() as usize;
+    if length == 0 || length > samples.len() {
+        return Err(format!("Cannot take a {:.2} s excerpt from {:.2} s of audio", seconds, samples.len() as f64 / sample_rate).into());
+    }
+
+    let start = (rng.next_f64() * (samples.len() - length + 1) as f64) as usize;
+    Ok((samples[start..start + length].to_vec(), start as f64 / sample_rate))
+}
+
+// Scales `noise` so that signal power over noise power equals `snr_db`, then adds it.
+// Silent audio is left untouched since no noise level satisfies the ratio.
+pub fn mix_at_snr(samples: &mut [f64], noise: &[f64], snr_db: f64) {
+    let (signal_power, noise_power) = (power(samples), power(&noise[..samples.len().min(noise.len())]));
+    if signal_power == 0.0 || noise_power == 0.0 {
+        return;
+    }
+
+    let scale = (signal_power / (noise_power * 10f64.powf(snr_db / 10.0))).sqrt();
+    for (sample, noise) in samples.iter_mut().zip(noise) {
+        *sample += noise * scale;
+    }
+}
+
+// 1/f noise from white noise (Paul Kellet's refined filter)
+fn pink_noise(length: usize, rng: &mut SeededRng) -> Vec<f64> {
+    let mut b = [0.0f64; 7];
+    (0..length).map(|_| {
+        let white = rng.gaussian();
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b.iter().sum::<f64>() + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }).collect()
+}
+
+// (F1, F2) in Hz of a few vowels
+const VOWEL_FORMANTS: [(f64, f64); 5] = [(730.0, 1090.0), (270.0, 2290.0), (530.0, 1840.0), (570.0, 840.0), (300.0, 870.0)];
+
+// Speech-like noise: each voice is a glottal pulse train shaped by two formant resonators
+// that change vowel every syllable, with short pauses between syllables
+fn babble(length: usize, sample_rate: f64, voices: usize, rng: &mut SeededRng) -> Vec<f64> {
+    let mut output = vec![0.0; length];
+    let max_frequency = 0.45 * sample_rate;
+
+    for _ in 0..voices.max(1) {
+        let pitch = rng.range(90.0, 250.0);
+        let mut phase = rng.next_f64();
+        let mut first_formant = Biquad::band_pass(VOWEL_FORMANTS[0].0.min(max_frequency), sample_rate, 5.0);
+        let mut second_formant = Biquad::band_pass(VOWEL_FORMANTS[0].1.min(max_frequency), sample_rate, 5.0);
+
+        let mut position = 0;
+        while position < length {
+            let syllable = ((rng.range(0.12, 0.3) * sample_rate) as usize).max(1);
+            let (f1, f2) = VOWEL_FORMANTS[(rng.next_u64() % VOWEL_FORMANTS.len() as u64) as usize];
+            first_formant.retune(&Biquad::band_pass((f1 * rng.range(0.9, 1.1)).min(max_frequency), sample_rate, 5.0));
+            second_formant.retune(&Biquad::band_pass((f2 * rng.range(0.9, 1.1)).min(max_frequency), sample_rate, 5.0));
+            let voiced = rng.next_f64() < 0.8;
+
+            for i in 0..syllable.min(length - position) {
+                phase += pitch / sample_rate;
+                let pulse = if phase >= 1.0 {
+                    phase -= 1.0;
+                    1.0
+                } else {
+                    0.0
+                };
+                let excitation = pulse + 0.05 * rng.gaussian();
+                let voice = first_formant.process(excitation) + 0.5 * second_formant.process(excitation);
+                if voiced {
+                    output[position + i] += voice * (PI * i as f64 / syllable as f64).sin();
+                }
+            }
+            position += syllable;
+        }
+    }
+
+    output
+}
+
+// Second-order IIR section with coefficients from the Audio EQ Cookbook
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0, x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0 }
+    }
+
+    fn low_pass(cutoff: f64, sample_rate: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let (cos, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+        Self::normalized((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    fn high_pass(cutoff: f64, sample_rate: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let (cos, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+        Self::normalized((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    // Unity gain at the centre frequency
+    fn band_pass(center: f64, sample_rate: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * center / sample_rate;
+        let (cos, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+        Self::normalized(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    // Takes another filter's coefficients while keeping this one's state
+    fn retune(&mut self, other: &Biquad) {
+        (self.b0, self.b1, self.b2, self.a1, self.a2) = (other.b0, other.b1, other.b2, other.a1, other.a2);
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+        (self.x2, self.x1, self.y2, self.y1) = (self.x1, x, self.y1, y);
+        y
+    }
+}
+
+// Q of the two sections of a 4th-order Butterworth filter
+const BUTTERWORTH_Q: [f64; 2] = [0.541_196_100_146_197, 1.306_562_964_876_376_5];
+
+fn butterworth(samples: &[f64], sample_rate: f64, cutoff: f64, section: fn(f64, f64, f64) -> Biquad) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    if cutoff <= 0.0 || cutoff >= sample_rate / 2.0 {
+        return Err(format!("Filter cutoff {} Hz must lie between 0 and {} Hz", cutoff, sample_rate / 2.0).into());
+    }
+
+    let mut sections: Vec<Biquad> = BUTTERWORTH_Q.iter().map(|&q| section(cutoff, sample_rate, q)).collect();
+    Ok(samples.iter().map(|&sample| sections.iter_mut().fold(sample, |x, biquad| biquad.process(x))).collect())
+}
+
+// Full linear convolution (length `signal + impulse_response - 1`) via FFT
+pub fn convolve(signal: &[f64], impulse_response: &[f64]) -> Vec<f64> {
+    if signal.is_empty() || impulse_response.is_empty() {
+        return Vec::new();
+    }
+    let output_length = signal.len() + impulse_response.len() - 1;
+    let fft_length = output_length.next_power_of_two();
+
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(fft_length);
+    let inverse = planner.plan_fft_inverse(fft_length);
+
+    let spectrum = |values: &[f64]| {
+        let mut buffer: Vec<Complex<f64>> = values.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        buffer.resize(fft_length, Complex::new(0.0, 0.0));
+        forward.process(&mut buffer);
+        buffer
+    };
+    let mut product: Vec<Complex<f64>> = spectrum(signal).iter().zip(spectrum(impulse_response)).map(|(a, b)| a * b).collect();
+    inverse.process(&mut product);
+
+    product[..output_length].iter().map(|c| c.re / fft_length as f64).collect()
+}
+
+fn reverb(samples: &[f64], sample_rate: f64, rt60: f64, wet: f64, rng: &mut SeededRng) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    if rt60 <= 0.0 || !(0.0..=1.0).contains(&wet) {
+        return Err("Reverb needs rt60 > 0 and wet between 0 and 1".into());
+    }
+
+    // Exponentially decaying noise after a direct-path impulse; ln(1000) gives 60 dB over rt60
+    let decay = 1000f64.ln() / rt60;
+    let length = ((rt60 * sample_rate).ceil() as usize).max(1);
+    let impulse_response: Vec<f64> = (0..length)
+        .map(|n| if n == 0 { 1.0 } else { rng.gaussian() * (-decay * n as f64 / sample_rate).exp() })
+        .collect();
+
+    // Keep the clip length and level: the tail past the end is dropped and the wet signal is
+    // scaled to the dry signal's power
+    let mut reverberant = convolve(samples, &impulse_response);
+    reverberant.truncate(samples.len());
+    let (dry_power, wet_power) = (power(samples), power(&reverberant));
+    let scale = if wet_power > 0.0 { (dry_power / wet_power).sqrt() } else { 0.0 };
+
+    Ok(samples.iter().zip(&reverberant).map(|(dry, reverberant)| (1.0 - wet) * dry + wet * scale * reverberant).collect())
+}
+
+fn bandlimit(samples: &[f64], sample_rate: f64, cutoff: f64, bits: u32) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    if cutoff <= 0.0 || bits == 0 || bits > 24 {
+        return Err("Bandlimit needs cutoff > 0 and between 1 and 24 bits".into());
+    }
+
+    // Sine window for analysis and synthesis at 50% overlap: the squared windows sum to one
+    let frame_length = if sample_rate > 16_000.0 { 1024 } else { 512 };
+    let hop = frame_length / 2;
+    let window: Vec<f64> = (0..frame_length).map(|n| (PI * (n as f64 + 0.5) / frame_length as f64).sin()).collect();
+    let cutoff_bin = cutoff / sample_rate * frame_length as f64;
+    let levels = (1u32 << (bits - 1)) as f64;
+
+    // Half a frame of padding on both ends so every sample is covered by two frames
+    let mut padded = vec![0.0; hop];
+    padded.extend_from_slice(samples);
+    padded.resize(padded.len().div_ceil(hop) * hop + hop, 0.0);
+    let mut output = vec![0.0; padded.len()];
+
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(frame_length);
+    let inverse = planner.plan_fft_inverse(frame_length);
+
+    for start in (0..=padded.len() - frame_length).step_by(hop) {
+        let mut spectrum: Vec<Complex<f64>> = (0..frame_length)
+            .map(|n| Complex::new(padded[start + n] * window[n], 0.0))
+            .collect();
+        forward.process(&mut spectrum);
+
+        let peak = spectrum.iter().map(|c| c.norm()).fold(0.0, f64::max);
+        let step = peak / levels;
+        for (bin, value) in spectrum.iter_mut().enumerate() {
+            // Bins k and N - k hold the same frequency
+            if bin.min(frame_length - bin) as f64 > cutoff_bin || step == 0.0 {
+                *value = Complex::new(0.0, 0.0);
+            } else {
+                *value = Complex::new((value.re / step).round() * step, (value.im / step).round() * step);
+            }
+        }
+
+        inverse.process(&mut spectrum);
+        for n in 0..frame_length {
+            output[start + n] += spectrum[n].re / frame_length as f64 * window[n];
+        }
+    }
+
+    Ok(output[hop..hop + samples.len()].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 8000.0;
+
+    fn sine(frequency: f64, seconds: f64) -> Vec<f64> {
+        (0..(seconds * SAMPLE_RATE) as usize).map(|n| 0.5 * (2.0 * PI * frequency * n as f64 / SAMPLE_RATE).sin()).collect()
+    }
+
+    fn degraded(samples: &[f64], step: Degradation) -> DegradedClip {
+        degrade(samples, SAMPLE_RATE, &[step], 7).unwrap()
+    }
+
+    // Output power over input power in dB, ignoring the filters' start-up
+    fn gain_db(input: &[f64], output: &[f64]) -> f64 {
+        let settled = input.len() / 4;
+        10.0 * (power(&output[settled..]) / power(&input[settled..])).log10()
+    }
+
+    fn zero_crossings(samples: &[f64]) -> usize {
+        samples.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count()
+    }
+
+    #[test]
+    fn noise_is_mixed_at_the_requested_snr() {
+        let signal = sine(440.0, 1.0);
+        for (step, snr_db) in [(Degradation::WhiteNoise { snr_db: 5.0 }, 5.0), (Degradation::PinkNoise { snr_db: -3.0 }, -3.0),
+                               (Degradation::Babble { snr_db: 10.0, voices: 4 }, 10.0)] {
+            let clip = degraded(&signal, step);
+            let noise: Vec<f64> = clip.samples.iter().zip(&signal).map(|(mixed, clean)| mixed - clean).collect();
+            let measured = 10.0 * (power(&signal) / power(&noise)).log10();
+            assert!((measured - snr_db).abs() < 1e-9, "SNR {:.3} dB, expected {} dB", measured, snr_db);
+        }
+
+        let mut silence = vec![0.0; 100];
+        mix_at_snr(&mut silence, &[1.0; 100], 0.0);
+        assert!(silence.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn gain_scales_and_clip_saturates_at_full_scale() {
+        let signal = sine(440.0, 0.5);
+        let quieter = degraded(&signal, Degradation::Gain { db: -6.0 });
+        assert!((gain_db(&signal, &quieter.samples) + 6.0).abs() < 1e-9);
+
+        let clipped = degraded(&signal, Degradation::Clip { drive_db: 12.0 });
+        assert!(clipped.samples.iter().all(|sample| sample.abs() <= 1.0));
+        assert!(clipped.samples.iter().filter(|sample| sample.abs() == 1.0).count() > signal.len() / 2);
+    }
+
+    #[test]
+    fn excerpts_have_the_requested_length_and_report_their_start() {
+        let signal: Vec<f64> = (0..4 * SAMPLE_RATE as usize).map(|n| n as f64).collect();
+        let clip = degraded(&signal, Degradation::Excerpt { seconds: 1.5 });
+        assert_eq!(clip.samples.len(), (1.5 * SAMPLE_RATE) as usize);
+        assert_eq!(clip.samples[0], clip.start * SAMPLE_RATE);
+        assert!(clip.start <= 2.5);
+
+        // After a speed change, the start is in the source's seconds
+        let sped_up = degrade(&signal, SAMPLE_RATE, &[Degradation::Speed { factor: 2.0 }, Degradation::Excerpt { seconds: 0.5 }], 7).unwrap();
+        let resampled = change_speed(&signal, 2.0).unwrap();
+        let (_, start) = random_excerpt(&resampled, SAMPLE_RATE, 0.5, &mut SeededRng::new(7)).unwrap();
+        assert_eq!(sped_up.start, 2.0 * start);
+
+        assert!(degrade(&signal, SAMPLE_RATE, &[Degradation::Excerpt { seconds: 5.0 }], 7).is_err());
+    }
+
+    #[test]
+    fn filters_pass_their_band_and_cut_the_other() {
+        let (low, high) = (sine(200.0, 1.0), sine(2500.0, 1.0));
+
+        let low_pass = Degradation::LowPass { cutoff_hz: 800.0 };
+        assert!(gain_db(&low, &degraded(&low, low_pass.clone()).samples).abs() < 0.5);
+        assert!(gain_db(&high, &degraded(&high, low_pass).samples) < -40.0);
+
+        let high_pass = Degradation::HighPass { cutoff_hz: 800.0 };
+        assert!(gain_db(&high, &degraded(&high, high_pass.clone()).samples).abs() < 0.5);
+        assert!(gain_db(&low, &degraded(&low, high_pass).samples) < -40.0);
+
+        assert!(degrade(&low, SAMPLE_RATE, &[Degradation::LowPass { cutoff_hz: 4000.0 }], 7).is_err());
+    }
+
+    #[test]
+    fn reverb_keeps_the_length_and_level() {
+        let signal = sine(440.0, 1.0);
+        let dry = degraded(&signal, Degradation::Reverb { rt60: 0.4, wet: 0.0 });
+        assert_eq!(dry.samples, signal);
+
+        let wet = degraded(&signal, Degradation::Reverb { rt60: 0.4, wet: 1.0 });
+        assert_eq!(wet.samples.len(), signal.len());
+        assert!((10.0 * (power(&wet.samples) / power(&signal)).log10()).abs() < 1e-9);
+        assert_ne!(wet.samples, signal);
+
+        assert!(degrade(&signal, SAMPLE_RATE, &[Degradation::Reverb { rt60: 0.4, wet: 1.5 }], 7).is_err());
+    }
+
+    #[test]
+    fn bandlimit_drops_what_lies_above_the_cutoff() {
+        let (low, high) = (sine(500.0, 1.0), sine(3000.0, 1.0));
+        let step = Degradation::Bandlimit { cutoff_hz: 2000.0, bits: 8 };
+
+        let kept = degraded(&low, step.clone());
+        assert_eq!(kept.samples.len(), low.len());
+        assert!(gain_db(&low, &kept.samples).abs() < 0.5);
+        assert!(gain_db(&high, &degraded(&high, step).samples) < -60.0);
+    }
+
+    #[test]
+    fn speed_changes_shorten_the_audio_and_raise_its_pitch() {
+        let signal = sine(400.0, 2.0);
+        let faster = degraded(&signal, Degradation::Speed { factor: 1.25 });
+
+        // Less the half sinc length the resampler holds back
+        let expected_length = signal.len() as f64 / 1.25;
+        assert!((faster.samples.len() as f64 - expected_length).abs() <= 128.0, "{} samples", faster.samples.len());
+        // Crossings per second rise with the frequency
+        let settled = 400;
+        let rate = |samples: &[f64]| zero_crossings(&samples[settled..samples.len() - settled]) as f64 / (samples.len() - 2 * settled) as f64;
+        assert!((rate(&faster.samples) / rate(&signal) - 1.25).abs() < 0.01);
+
+        assert!(change_speed(&signal, 0.0).is_err());
+    }
+}
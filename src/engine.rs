@@ -0,0 +1,244 @@
+use crate::match_song::{match_song, rank_matches, HashWeights, MatchConfig};
+pub use crate::match_song::MatchCandidate;
+use crate::stop_list::StopList;
+use crate::calibration::Calibration;
+use crate::fingerprint::{Fingerprint, FingerprintScheme, SchemeParameters};
+use crate::constants::{MAX_TEMPO_CHANGE, TEMPO_STEP};
+use crate::catalogue_stats::{catalogue_stats, CatalogueStats};
+use crate::fingerprint_store::{open_store, open_store_from_env, FingerprintStore, FingerprintRow, SongInfo, SongMetadata};
+use serde::Serialize;
+
+// A clip's hashes after the stop-list, with the stored rows they hit
+struct Query {
+    rows: Vec<FingerprintRow>,
+    hashes: Vec<u64>,
+    anchor_times: Vec<f64>,
+    duration: f64,
+    weights: HashWeights,
+}
+
+// A recognized song and where the clip lies within it
+#[derive(Serialize, Debug, Clone)]
+pub struct Recognition {
+    #[serde(flatten)]
+    pub song: SongInfo,
+    // Estimated probability (%) that this is the right song; see `Calibration`
+    pub confidence: f64,
+    // Position of the clip within the song, in seconds to STFT frame precision
+    pub start: f64,
+    pub end: f64,
+    // Song seconds per clip second: any speed the triplet scheme allows, and within a couple
+    // of percent of 1 with the pair scheme
+    pub tempo_factor: f64,
+}
+
+// Long-lived handle owning the fingerprint store. Backends set up their schema once when
+// they are created; every ingest and recognize call afterwards reuses the same store.
+pub struct ShazamEngine {
+    store: Box<dyn FingerprintStore>,
+    stop_list: Option<StopList>,
+    calibration: Calibration,
+    scheme: FingerprintScheme,
+}
+
+impl ShazamEngine {
+    pub fn new(store: Box<dyn FingerprintStore>) -> Self {
+        Self { store, stop_list: None, calibration: Calibration::default(), scheme: FingerprintScheme::default() }
+    }
+
+    // Store from SHAZAM_DATABASE_URL (see `open_store_from_env`), the rest as `with_env_settings`
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(open_store_from_env()?).with_store_scheme()?.with_env_settings()
+    }
+
+    // Stop-list from SHAZAM_STOP_LIST_PERCENT (see `StopList::from_env`), confidence
+    // calibration from SHAZAM_CALIBRATION (see `Calibration::from_env`) and hashing scheme
+    // from SHAZAM_FINGERPRINT_SCHEME (see `FingerprintScheme::from_env`), where set
+    pub fn with_env_settings(mut self) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(stop_list) = StopList::from_env()? {
+            self = self.with_stop_list(stop_list);
+        }
+        if let Some(calibration) = Calibration::from_env()? {
+            self = self.with_calibration(calibration);
+        }
+        if let Some(scheme) = FingerprintScheme::from_env()? {
+            self = self.with_scheme(scheme);
+            self.check_store()?;
+        }
+        Ok(self)
+    }
+
+    pub fn from_url(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(open_store(url)?).with_store_scheme()
+    }
+
+    // Hashes with the scheme recorded in the store, if any; errors when the store's
+    // fingerprints come from another scheme version
+    pub fn with_store_scheme(mut self) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(parameters) = self.store.scheme_parameters()? {
+            self.scheme = parameters.hashing;
+        }
+        self.check_store()?;
+        Ok(self)
+    }
+
+    pub fn with_stop_list(mut self, stop_list: StopList) -> Self {
+        self.stop_list = Some(stop_list);
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    // Hashing scheme of the catalogue: used to fingerprint audio, and required of fingerprints
+    // computed elsewhere
+    pub fn with_scheme(mut self, scheme: FingerprintScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    pub fn scheme(&self) -> FingerprintScheme {
+        self.scheme
+    }
+
+    // Errors when the store holds fingerprints this engine cannot compare its own with
+    pub fn check_store(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.store.scheme_parameters()? {
+            Some(stored) => stored.check_compatible(&SchemeParameters::current(self.scheme))
+                .map_err(|e| format!("The catalogue cannot be used with this engine: {}", e).into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn store(&self) -> &dyn FingerprintStore {
+        self.store.as_ref()
+    }
+
+    // Catalogue summary including the `top_hashes` most widespread hashes
+    pub fn stats(&self, top_hashes: usize) -> Result<CatalogueStats, Box<dyn std::error::Error>> {
+        catalogue_stats(self.store(), top_hashes)
+    }
+
+    // Fingerprints a WAV file at any path with the engine's scheme
+    pub fn fingerprint_wav_file(&self, path: &str) -> Result<Fingerprint, Box<dyn std::error::Error>> {
+        Fingerprint::from_wav_file_using(path, self.scheme)
+    }
+
+    pub fn ingest(&self, metadata: &SongMetadata, audio_path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+        let fingerprint = Fingerprint::from_audio_file_using(audio_path, self.scheme)?;
+        self.ingest_fingerprint(metadata, &fingerprint)
+    }
+
+    pub fn recognize(&self, audio_path: &str) -> Result<SongInfo, Box<dyn std::error::Error>> {
+        Ok(self.recognize_audio(audio_path)?.song)
+    }
+
+    // Like `recognize`, with the clip's position in the song
+    pub fn recognize_audio(&self, audio_path: &str) -> Result<Recognition, Box<dyn std::error::Error>> {
+        let fingerprint = Fingerprint::from_audio_file_using(audio_path, self.scheme)?;
+        self.recognize_match(&fingerprint)
+    }
+
+    // Stores a fingerprint computed elsewhere, e.g. on a client device. A song without a
+    // duration takes the fingerprint's, which bounds where recognized clips end. Hashes the stop-list
+    // drops, judged on the catalogue as it will be with this song, are kept out of the stored
+    // pairs but still counted for the song. The first song stored records the engine's scheme
+    // in the store.
+    pub fn ingest_fingerprint(&self, metadata: &SongMetadata, fingerprint: &Fingerprint) -> Result<u64, Box<dyn std::error::Error>> {
+        self.check_scheme(fingerprint)?;
+        self.store.claim_scheme(&SchemeParameters::current(self.scheme))?;
+        let metadata = &SongMetadata { duration: metadata.duration.or(Some(fingerprint.duration)), ..metadata.clone() };
+
+        let stop_list = match &self.stop_list {
+            Some(stop_list) => stop_list,
+            None => return self.store.ingest_song(metadata, &fingerprint.hashes, &fingerprint.anchor_times),
+        };
+        let catalogue_songs = self.store.song_count()? + 1;
+        let mut song_counts = self.store.hash_song_counts(&fingerprint.hashes)?;
+        for key in &fingerprint.hashes {
+            song_counts.entry(*key).or_insert(0);
+        }
+        for count in song_counts.values_mut() {
+            *count += 1;
+        }
+        let (hashes, anchor_times, stopped) = stop_list.split(&fingerprint.hashes, &fingerprint.anchor_times, &song_counts, catalogue_songs);
+        self.store.ingest_song_with_stopped(metadata, &hashes, &anchor_times, &stopped)
+    }
+
+    pub fn recognize_fingerprint(&self, fingerprint: &Fingerprint) -> Result<SongInfo, Box<dyn std::error::Error>> {
+        Ok(self.recognize_match(fingerprint)?.song)
+    }
+
+    // Best matching song with the clip's position in it; errors when no song matches
+    pub fn recognize_match(&self, fingerprint: &Fingerprint) -> Result<Recognition, Box<dyn std::error::Error>> {
+        let query = self.query(fingerprint)?;
+        let mut candidate = match_song(query.rows, &query.hashes, &query.anchor_times, query.duration, Some(&query.weights), Some(self.match_config()))?
+            .ok_or("No matching song found")?;
+        log::info!("Confidence of the matched song: {:.2}", candidate.confidence);
+        let song = self.store.song_info(candidate.song_id)?;
+        candidate.clamp_to_song(self.song_duration(&song)?);
+
+        Ok(Recognition {
+            song,
+            confidence: candidate.confidence,
+            start: candidate.start,
+            end: candidate.end,
+            tempo_factor: candidate.tempo_factor,
+        })
+    }
+
+    // Every song that passes the match thresholds, best first; empty when nothing matches
+    pub fn recognize_candidates(&self, fingerprint: &Fingerprint) -> Result<Vec<MatchCandidate>, Box<dyn std::error::Error>> {
+        let query = self.query(fingerprint)?;
+        let mut candidates = rank_matches(&query.rows, &query.hashes, &query.anchor_times, query.duration, Some(&query.weights), &self.match_config());
+        for candidate in candidates.iter_mut() {
+            let song = self.store.song_info(candidate.song_id)?;
+            candidate.clamp_to_song(self.song_duration(&song)?);
+        }
+        Ok(candidates)
+    }
+
+    // The song's recorded duration, or for songs stored without one the last anchor time
+    fn song_duration(&self, song: &SongInfo) -> Result<f64, Box<dyn std::error::Error>> {
+        if let Some(duration) = song.metadata.duration {
+            return Ok(duration);
+        }
+        let (_, anchor_times) = self.store.song_hashes(song.id)?;
+        Ok(anchor_times.into_iter().fold(0.0, f64::max))
+    }
+
+    fn match_config(&self) -> MatchConfig {
+        let config = MatchConfig::default().with_calibration(self.calibration.clone());
+        match self.scheme {
+            FingerprintScheme::Pairs => config,
+            FingerprintScheme::Triplets => config.with_tempo_search(MAX_TEMPO_CHANGE, TEMPO_STEP),
+        }
+    }
+
+    // The fingerprint must come from the engine's scheme, and the store's fingerprints too
+    fn check_scheme(&self, fingerprint: &Fingerprint) -> Result<(), Box<dyn std::error::Error>> {
+        fingerprint.check_scheme()?;
+        if fingerprint.scheme != self.scheme {
+            return Err(format!("Fingerprint hashes {}, this catalogue hashes {}", fingerprint.scheme, self.scheme).into());
+        }
+        self.check_store()
+    }
+
+    // Applies the stop-list to the clip's hashes and fetches the stored rows they hit
+    fn query(&self, fingerprint: &Fingerprint) -> Result<Query, Box<dyn std::error::Error>> {
+        self.check_scheme(fingerprint)?;
+
+        let catalogue_songs = self.store.song_count()?;
+        let song_counts = self.store.hash_song_counts(&fingerprint.hashes)?;
+        let (hashes, anchor_times) = match &self.stop_list {
+            Some(stop_list) => stop_list.filter(&fingerprint.hashes, &fingerprint.anchor_times, &song_counts, catalogue_songs),
+            None => (fingerprint.hashes.clone(), fingerprint.anchor_times.clone()),
+        };
+        let weights = HashWeights::new(catalogue_songs, song_counts);
+
+        let rows: Vec<FingerprintRow> = self.store.lookup_hashes(&hashes)?;
+        Ok(Query { rows, hashes, anchor_times, duration: fingerprint.duration, weights })
+    }
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use crate::calibration::CalibrationSample;
+use crate::engine::ShazamEngine;
+use crate::fingerprint_store::SongInfo;
+
+// A query clip and the song it should be recognized as. Clips of songs outside the
+// catalogue leave `song_id`, `title` and `artist` empty.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LabelledClip {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub song_id: Option<u64>,
+    // Used when `song_id` is empty: the catalogue song with this title (and artist, if given)
+    #[serde(default, alias = "name")]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub artist: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvaluationOptions {
+    // A clip counts as found within the top k when its song is among the first k candidates
+    pub top_k: usize,
+    // Candidates below this confidence are treated as "no match"
+    pub min_confidence: f64,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self { top_k: 5, min_confidence: 0.0 }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RankedSong {
+    pub song_id: u64,
+    pub confidence: f64,
+    // The statistics behind `confidence`, for refitting the calibration
+    pub hash_fraction: f64,
+    pub margin: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClipResult {
+    pub path: PathBuf,
+    pub expected_song_id: Option<u64>,
+    // Best candidate at or above the confidence threshold
+    pub predicted_song_id: Option<u64>,
+    pub confidence: Option<f64>,
+    // 1-based position of the expected song among the candidates
+    pub rank: Option<usize>,
+    pub candidates: Vec<RankedSong>,
+    pub fingerprint_ms: f64,
+    pub match_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ClipResult {
+    fn latency_ms(&self) -> f64 {
+        self.fingerprint_ms + self.match_ms
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn from_values(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(f64::total_cmp);
+
+        // Nearest-rank percentile, as in `catalogue_stats::Distribution`
+        let percentile = |p: f64| values[((p / 100.0 * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+        Self {
+            count: values.len(),
+            min: values[0],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+// Rates are fractions between 0 and 1, and None when no clip falls in their denominator.
+// Clips that could not be processed (see `failed_clips`) are left out of every rate.
+#[derive(Serialize, Debug, Clone)]
+pub struct EvaluationMetrics {
+    pub clips: usize,
+    pub in_catalogue_clips: usize,
+    pub out_of_catalogue_clips: usize,
+    pub failed_clips: usize,
+    pub top_k: usize,
+    pub min_confidence: f64,
+    pub top1_accuracy: Option<f64>,
+    pub top_k_accuracy: Option<f64>,
+    // In-catalogue clips answered with the wrong song
+    pub misidentification_rate: Option<f64>,
+    // Out-of-catalogue clips answered with any song
+    pub false_positive_rate: Option<f64>,
+    pub confidence_correct: Summary,
+    pub confidence_incorrect: Summary,
+    pub confidence_out_of_catalogue: Summary,
+    pub latency_ms: Summary,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EvaluationReport {
+    pub metrics: EvaluationMetrics,
+    pub clips: Vec<ClipResult>,
+}
+
+impl EvaluationReport {
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    // The top candidate of every clip that had one, labelled with whether it was the expected
+    // song; input for `Calibration::fit`. Clips with no candidate at all are left out.
+    pub fn calibration_samples(&self) -> Vec<CalibrationSample> {
+        self.clips.iter()
+            .filter(|clip| clip.error.is_none())
+            .filter_map(|clip| clip.candidates.first().map(|top| CalibrationSample {
+                hash_fraction: top.hash_fraction,
+                margin: top.margin,
+                correct: clip.expected_song_id == Some(top.song_id),
+            }))
+            .collect()
+    }
+}
+
+// Reads a `.csv` (header `path,song_id,title,artist`) or `.json` list of labelled clips.
+// Relative paths are resolved against the list's directory.
+pub fn read_clips(list_path: &Path) -> Result<Vec<LabelledClip>, Box<dyn std::error::Error>> {
+    let extension = list_path.extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    let mut clips: Vec<LabelledClip> = match extension.as_str() {
+        "csv" => {
+            let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(list_path)?;
+            let mut clips = Vec::new();
+            for (row_number, row) in reader.deserialize::<LabelledClip>().enumerate() {
+                clips.push(row.map_err(|e| format!("Invalid clip row {}: {}", row_number + 2, e))?);
+            }
+            clips
+        }
+        "json" => serde_json::from_reader(BufReader::new(File::open(list_path)?))
+            .map_err(|e| format!("Invalid clip list {}: {}", list_path.display(), e))?,
+        _ => return Err(format!("Clip list must be a .csv or .json file: {}", list_path.display()).into()),
+    };
+
+    let base = list_path.parent().unwrap_or(Path::new(""));
+    for clip in clips.iter_mut() {
+        if clip.path.is_relative() {
+            clip.path = base.join(&clip.path);
+        }
+        clip.title = clip.title.take().filter(|title| !title.is_empty());
+        clip.artist = clip.artist.take().filter(|artist| !artist.is_empty());
+    }
+    Ok(clips)
+}
+
+fn expected_song(clip: &LabelledClip, songs: &[SongInfo]) -> Result<Option<u64>, String> {
+    if let Some(song_id) = clip.song_id {
+        return match songs.iter().any(|song| song.id == song_id) {
+            true => Ok(Some(song_id)),
+            false => Err(format!("Song {} is not in the catalogue", song_id)),
+        };
+    }
+    let Some(title) = &clip.title else {
+        return Ok(None);
+    };
+
+    songs.iter()
+        .find(|song| &song.metadata.name == title && clip.artist.as_ref().is_none_or(|artist| &song.metadata.artist == artist))
+        .map(|song| Some(song.id))
+        .ok_or_else(|| format!("No song titled '{}' in the catalogue", title))
+}
+
+fn evaluate_clip(engine: &ShazamEngine, clip: &LabelledClip, expected_song_id: Option<u64>, options: &EvaluationOptions) -> Result<ClipResult, Box<dyn std::error::Error>> {
+    let path = clip.path.to_str().ok_or("Path is not valid UTF-8")?;
+
+    let started = Instant::now();
+    let fingerprint = engine.fingerprint_wav_file(path)?;
+    let fingerprinted = Instant::now();
+    let candidates = engine.recognize_candidates(&fingerprint)?;
+    let matched = Instant::now();
+
+    let candidates: Vec<RankedSong> = candidates.into_iter()
+        .filter(|candidate| candidate.confidence >= options.min_confidence)
+        .map(|candidate| RankedSong {
+            song_id: candidate.song_id,
+            confidence: candidate.confidence,
+            hash_fraction: candidate.hash_fraction,
+            margin: candidate.margin,
+        })
+        .collect();
+    let rank = expected_song_id.and_then(|song_id| candidates.iter().position(|candidate| candidate.song_id == song_id).map(|index| index + 1));
+
+    Ok(ClipResult {
+        path: clip.path.clone(),
+        expected_song_id,
+        predicted_song_id: candidates.first().map(|candidate| candidate.song_id),
+        confidence: candidates.first().map(|candidate| candidate.confidence),
+        rank,
+        candidates: candidates.into_iter().take(options.top_k.max(1)).collect(),
+        fingerprint_ms: (fingerprinted - started).as_secs_f64() * 1000.0,
+        match_ms: (matched - fingerprinted).as_secs_f64() * 1000.0,
+        error: None,
+    })
+}
+
+fn rate(count: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| count as f64 / total as f64)
+}
+
+fn metrics(clips: &[ClipResult], options: &EvaluationOptions) -> EvaluationMetrics {
+    let processed: Vec<&ClipResult> = clips.iter().filter(|clip| clip.error.is_none()).collect();
+    let (in_catalogue, out_of_catalogue): (Vec<&ClipResult>, Vec<&ClipResult>) =
+        processed.iter().partition(|clip| clip.expected_song_id.is_some());
+
+    let correct: Vec<&&ClipResult> = in_catalogue.iter().filter(|clip| clip.rank == Some(1)).collect();
+    let in_top_k = in_catalogue.iter().filter(|clip| clip.rank.is_some_and(|rank| rank <= options.top_k)).count();
+    let misidentified: Vec<&&ClipResult> = in_catalogue.iter()
+        .filter(|clip| clip.predicted_song_id.is_some() && clip.rank != Some(1))
+        .collect();
+    let false_positives: Vec<&&ClipResult> = out_of_catalogue.iter().filter(|clip| clip.predicted_song_id.is_some()).collect();
+
+    let confidences = |clips: &[&&ClipResult]| Summary::from_values(clips.iter().filter_map(|clip| clip.confidence).collect());
+
+    EvaluationMetrics {
+        clips: clips.len(),
+        in_catalogue_clips: in_catalogue.len(),
+        out_of_catalogue_clips: out_of_catalogue.len(),
+        failed_clips: clips.len() - processed.len(),
+        top_k: options.top_k,
+        min_confidence: options.min_confidence,
+        top1_accuracy: rate(correct.len(), in_catalogue.len()),
+        top_k_accuracy: rate(in_top_k, in_catalogue.len()),
+        misidentification_rate: rate(misidentified.len(), in_catalogue.len()),
+        false_positive_rate: rate(false_positives.len(), out_of_catalogue.len()),
+        confidence_correct: confidences(&correct),
+        confidence_incorrect: confidences(&misidentified),
+        confidence_out_of_catalogue: confidences(&false_positives),
+        latency_ms: Summary::from_values(processed.iter().map(|clip| clip.latency_ms()).collect()),
+    }
+}
+
+// Recognizes every clip against the engine's catalogue, one at a time so the latencies are
+// comparable. A clip that cannot be processed is recorded with its error and skipped.
+pub fn evaluate(engine: &ShazamEngine, clips: &[LabelledClip], options: &EvaluationOptions) -> Result<EvaluationReport, Box<dyn std::error::Error>> {
+    let songs = engine.store().list_songs()?;
+
+    let mut results = Vec::with_capacity(clips.len());
+    for clip in clips {
+        let result = expected_song(clip, &songs)
+            .map_err(|e| e.into())
+            .and_then(|expected_song_id| evaluate_clip(engine, clip, expected_song_id, options));
+        results.push(result.unwrap_or_else(|e| ClipResult {
+            path: clip.path.clone(),
+            expected_song_id: clip.song_id,
+            predicted_song_id: None,
+            confidence: None,
+            rank: None,
+            candidates: Vec::new(),
+            fingerprint_ms: 0.0,
+            match_ms: 0.0,
+            error: Some(e.to_string()),
+        }));
+    }
+
+    Ok(EvaluationReport { metrics: metrics(&results, options), clips: results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint_store::SongMetadata;
+    use crate::memory_store::MemoryStore;
+
+    fn labelled(path: &str, song_id: Option<u64>, title: Option<&str>, artist: Option<&str>) -> LabelledClip {
+        LabelledClip { path: PathBuf::from(path), song_id, title: title.map(str::to_string), artist: artist.map(str::to_string) }
+    }
+
+    // A processed clip whose candidates are (song, confidence) pairs, best first
+    fn result(expected_song_id: Option<u64>, candidates: &[(u64, f64)], latency_ms: f64) -> ClipResult {
+        let candidates: Vec<RankedSong> = candidates.iter()
+            .map(|&(song_id, confidence)| RankedSong { song_id, confidence, hash_fraction: 0.0, margin: 0.0 })
+            .collect();
+        ClipResult {
+            path: PathBuf::from("clip.wav"),
+            expected_song_id,
+            predicted_song_id: candidates.first().map(|candidate| candidate.song_id),
+            confidence: candidates.first().map(|candidate| candidate.confidence),
+            rank: expected_song_id.and_then(|song_id| candidates.iter().position(|candidate| candidate.song_id == song_id).map(|index| index + 1)),
+            candidates,
+            fingerprint_ms: latency_ms / 2.0,
+            match_ms: latency_ms / 2.0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn resolves_labels_by_id_or_title() {
+        let songs = vec![
+            SongInfo { id: 1, metadata: SongMetadata::new("Song", "First artist") },
+            SongInfo { id: 2, metadata: SongMetadata::new("Song", "Second artist") },
+        ];
+
+        assert_eq!(expected_song(&labelled("a.wav", Some(2), None, None), &songs), Ok(Some(2)));
+        assert!(expected_song(&labelled("a.wav", Some(3), None, None), &songs).is_err());
+        assert_eq!(expected_song(&labelled("a.wav", None, Some("Song"), None), &songs), Ok(Some(1)));
+        assert_eq!(expected_song(&labelled("a.wav", None, Some("Song"), Some("Second artist")), &songs), Ok(Some(2)));
+        assert!(expected_song(&labelled("a.wav", None, Some("Other"), None), &songs).is_err());
+        assert_eq!(expected_song(&labelled("a.wav", None, None, None), &songs), Ok(None));
+    }
+
+    #[test]
+    fn computes_accuracy_and_false_positive_rates() {
+        let mut failed = result(Some(1), &[], 0.0);
+        failed.error = Some("unreadable".to_string());
+        let clips = vec![
+            result(Some(1), &[(1, 90.0), (2, 10.0)], 10.0),
+            result(Some(2), &[(1, 60.0), (3, 20.0), (2, 5.0)], 20.0),
+            result(Some(3), &[(1, 40.0), (2, 30.0), (4, 20.0), (5, 10.0)], 30.0),
+            result(Some(4), &[], 40.0),
+            result(None, &[(5, 30.0)], 50.0),
+            result(None, &[], 60.0),
+            failed,
+        ];
+        let metrics = metrics(&clips, &EvaluationOptions { top_k: 3, min_confidence: 0.0 });
+
+        assert_eq!((metrics.clips, metrics.in_catalogue_clips, metrics.out_of_catalogue_clips, metrics.failed_clips), (7, 4, 2, 1));
+        assert_eq!(metrics.top1_accuracy, Some(0.25));
+        // The third clip's song is ranked fourth, past the top 3
+        assert_eq!(metrics.top_k_accuracy, Some(0.5));
+        assert_eq!(metrics.misidentification_rate, Some(0.5));
+        assert_eq!(metrics.false_positive_rate, Some(0.5));
+        assert_eq!(metrics.confidence_correct, Summary::from_values(vec![90.0]));
+        assert_eq!(metrics.confidence_incorrect, Summary::from_values(vec![60.0, 40.0]));
+        assert_eq!(metrics.confidence_out_of_catalogue, Summary::from_values(vec![30.0]));
+        assert_eq!(metrics.latency_ms, Summary { count: 6, min: 10.0, mean: 35.0, median: 30.0, p90: 60.0, p99: 60.0, max: 60.0 });
+    }
+
+    #[test]
+    fn leaves_rates_empty_without_clips_to_rate() {
+        let metrics = metrics(&[result(None, &[], 5.0)], &EvaluationOptions::default());
+        assert_eq!((metrics.top1_accuracy, metrics.top_k_accuracy, metrics.misidentification_rate), (None, None, None));
+        assert_eq!(metrics.false_positive_rate, Some(0.0));
+        assert_eq!(Summary::from_values(Vec::new()), Summary::default());
+    }
+
+    #[test]
+    fn summarizes_with_nearest_rank_percentiles() {
+        let summary = Summary::from_values((1..=20).rev().map(f64::from).collect());
+        assert_eq!(summary, Summary { count: 20, min: 1.0, mean: 10.5, median: 10.0, p90: 18.0, p99: 20.0, max: 20.0 });
+    }
+
+    #[test]
+    fn records_clips_that_cannot_be_processed() {
+        let engine = ShazamEngine::new(Box::new(MemoryStore::new()));
+        let clips = vec![labelled("missing.wav", None, None, None), labelled("missing.wav", None, Some("Unknown"), None)];
+        let report = evaluate(&engine, &clips, &EvaluationOptions::default()).unwrap();
+
+        assert_eq!(report.metrics.failed_clips, 2);
+        assert!(report.clips.iter().all(|clip| clip.error.is_some()));
+        assert_eq!(report.clips[1].error.as_deref(), Some("No song titled 'Unknown' in the catalogue"));
+        assert!(report.calibration_samples().is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use crate::audio_processing;
+use crate::make_spectr;
+use crate::process_spectr;
+use crate::create_hash;
+use crate::create_triplets;
+use crate::constants::*;
+use crate::index_file::{read_varint, write_varint, time_to_frame, frame_to_time};
+
+pub use crate::constants::FINGERPRINT_SCHEME_VERSION;
+
+// Binary layout (little-endian): MAGIC, format version (u32), scheme version (u32),
+// hashing scheme (u32, see `FingerprintScheme::code`), duration in seconds (f64), hash
+// count (u32), then per hash the key (u64) and the anchor time as a varint frame delta
+// from the previous hash (hashes are in time order). Version 1 had no hashing scheme field
+// and held scheme 1 pairs, which no catalogue of this build can match; it is rejected.
+const MAGIC: &[u8; 4] = b"SHZF";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LEN: usize = 28;
+
+// How peaks are turned into hashes. A catalogue only matches clips hashed the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FingerprintScheme {
+    // Anchor/target peak pairs keyed by both frequencies and their time delta (`create_pairs`)
+    #[default]
+    Pairs,
+    // Peak triplets keyed by frequency and time ratios only (`create_triplets`), so clips
+    // played a few percent faster or slower, or pitch-shifted, still match. Matching then
+    // also estimates the tempo factor. Hashes are less distinctive, so lookups return more
+    // rows and recognition is slower.
+    Triplets,
+}
+
+impl FingerprintScheme {
+    pub(crate) fn code(self) -> u32 {
+        match self {
+            FingerprintScheme::Pairs => 0,
+            FingerprintScheme::Triplets => 1,
+        }
+    }
+
+    pub(crate) fn from_code(code: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        match code {
+            0 => Ok(FingerprintScheme::Pairs),
+            1 => Ok(FingerprintScheme::Triplets),
+            _ => Err(format!("Unknown fingerprint hashing scheme {}", code).into()),
+        }
+    }
+
+    // The scheme named by SHAZAM_FINGERPRINT_SCHEME, if set
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::env::var("SHAZAM_FINGERPRINT_SCHEME") {
+            Ok(value) => Ok(Some(value.parse()?)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl std::fmt::Display for FingerprintScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FingerprintScheme::Pairs => "pairs",
+            FingerprintScheme::Triplets => "triplets",
+        })
+    }
+}
+
+impl std::str::FromStr for FingerprintScheme {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "pairs" => Ok(FingerprintScheme::Pairs),
+            "triplets" => Ok(FingerprintScheme::Triplets),
+            _ => Err(format!("Unknown fingerprint scheme '{}' (expected pairs or triplets)", value)),
+        }
+    }
+}
+
+// Parameters that decide which hashes a recording produces. Fingerprints are only
+// comparable between catalogues built with identical parameters; stores, index files and
+// catalogue exports record them with their fingerprints.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SchemeParameters {
+    pub version: u32,
+    // Stores and exports from before the hashing scheme was recorded always held pairs
+    #[serde(default)]
+    pub hashing: FingerprintScheme,
+    pub sample_rate: f64,
+    pub frame_length: usize,
+    pub hop_length: usize,
+    pub target_zone_frames: usize,
+    pub max_targets_per_anchor: usize,
+}
+
+impl SchemeParameters {
+    // The parameters of this build hashing with `hashing`
+    pub fn current(hashing: FingerprintScheme) -> Self {
+        Self {
+            version: FINGERPRINT_SCHEME_VERSION,
+            hashing,
+            sample_rate: OUTPUT_SAMPLE_RATE,
+            frame_length: FRAME_LENGTH,
+            hop_length: HOP_LENGTH,
+            target_zone_frames: TARGET_ZONE_FRAMES,
+            max_targets_per_anchor: MAX_TARGETS_PER_ANCHOR,
+        }
+    }
+
+    // Errors unless fingerprints made with `other` can be compared with these
+    pub fn check_compatible(&self, other: &SchemeParameters) -> Result<(), Box<dyn std::error::Error>> {
+        if self.version != other.version || self.hashing != other.hashing {
+            return Err(format!("Fingerprint scheme {} ({}) is not comparable with scheme {} ({})", self.version, self.hashing, other.version, other.hashing).into());
+        }
+        if self != other {
+            return Err(format!("Fingerprint scheme parameters differ: {:?} and {:?}", self, other).into());
+        }
+        Ok(())
+    }
+}
+
+// Hashes of one recording, detached from any store. Computing it needs only the audio, so
+// clients can fingerprint locally and send the result for ingest or recognition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub scheme_version: u32,
+    #[serde(default)]
+    pub scheme: FingerprintScheme,
+    // Length of the fingerprinted audio in seconds
+    pub duration: f64,
+    pub hashes: Vec<u64>,
+    // Anchor time in seconds of each hash, parallel to `hashes`
+    pub anchor_times: Vec<f64>,
+}
+
+impl Fingerprint {
+    pub fn from_peaks(peaks: &[Vec<usize>], duration: f64) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_peaks_using(peaks, duration, FingerprintScheme::Pairs)
+    }
+
+    pub fn from_peaks_using(peaks: &[Vec<usize>], duration: f64, scheme: FingerprintScheme) -> Result<Self, Box<dyn std::error::Error>> {
+        let (hashes, anchor_times) = match scheme {
+            FingerprintScheme::Pairs => create_hash::create_pairs(peaks)?,
+            FingerprintScheme::Triplets => create_triplets::create_triplets(peaks)?,
+        };
+        Ok(Self { scheme_version: FINGERPRINT_SCHEME_VERSION, scheme, duration, hashes, anchor_times })
+    }
+
+    // Fingerprints mono samples at any sample rate
+    pub fn from_samples(samples: &[f64], sample_rate: f64) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_samples_using(samples, sample_rate, FingerprintScheme::Pairs)
+    }
+
+    pub fn from_samples_using(samples: &[f64], sample_rate: f64, scheme: FingerprintScheme) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_resampled(resample(samples, sample_rate)?, scheme)
+    }
+
+    // Fingerprints a WAV file from the shared audio directory
+    pub fn from_audio_file(audio_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_audio_file_using(audio_path, FingerprintScheme::Pairs)
+    }
+
+    pub fn from_audio_file_using(audio_path: &str, scheme: FingerprintScheme) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_resampled(audio_processing::process_audio(audio_path)?, scheme)
+    }
+
+    // Fingerprints a WAV file at any path
+    pub fn from_wav_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_wav_file_using(path, FingerprintScheme::Pairs)
+    }
+
+    pub fn from_wav_file_using(path: &str, scheme: FingerprintScheme) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_resampled(audio_processing::process_wav_file(path)?, scheme)
+    }
+
+    fn from_resampled(audio: Vec<Vec<f64>>, scheme: FingerprintScheme) -> Result<Self, Box<dyn std::error::Error>> {
+        let duration = audio.first().ok_or("No audio channel to fingerprint")?.len() as f64 / OUTPUT_SAMPLE_RATE;
+        Self::from_peaks_using(&peaks(audio)?, duration, scheme)
+    }
+
+    // The hashes anchored from `start` to `end` seconds, timed from `start`: the fingerprint of
+    // that stretch of the audio, as if it had been recorded on its own
+    pub fn excerpt(&self, start: f64, end: f64) -> Self {
+        let end = end.min(self.duration);
+        let (hashes, anchor_times) = self.hashes.iter().zip(&self.anchor_times)
+            .filter(|&(_, &time)| time >= start && time < end)
+            .map(|(&hash, &time)| (hash, time - start))
+            .unzip();
+        Self { scheme_version: self.scheme_version, scheme: self.scheme, duration: (end - start).max(0.0), hashes, anchor_times }
+    }
+
+    // Errors unless the fingerprint can be compared with hashes produced by this build
+    pub fn check_scheme(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.scheme_version != FINGERPRINT_SCHEME_VERSION {
+            return Err(format!("Fingerprint uses scheme {}, this build uses {}", self.scheme_version, FINGERPRINT_SCHEME_VERSION).into());
+        }
+        if self.hashes.len() != self.anchor_times.len() {
+            return Err("Fingerprint hashes and anchor times must have the same length".into());
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.hashes.len() != self.anchor_times.len() {
+            return Err("Fingerprint hashes and anchor times must have the same length".into());
+        }
+
+        let mut pairs: Vec<(u32, u64)> = self.anchor_times.iter()
+            .map(|&time| time_to_frame(time))
+            .zip(self.hashes.iter().copied())
+            .collect();
+        pairs.sort_unstable();
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(HEADER_LEN + pairs.len() * 10);
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&self.scheme_version.to_le_bytes());
+        buffer.extend_from_slice(&self.scheme.code().to_le_bytes());
+        buffer.extend_from_slice(&self.duration.to_le_bytes());
+        buffer.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+
+        let mut previous_frame = 0u32;
+        for (anchor_frame, hash) in pairs {
+            buffer.extend_from_slice(&hash.to_le_bytes());
+            write_varint(&mut buffer, (anchor_frame - previous_frame) as u64);
+            previous_frame = anchor_frame;
+        }
+
+        Ok(buffer)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err("Data is not a binary fingerprint".into());
+        }
+        let format_version = u32::from_le_bytes(bytes[4..8].try_into()?);
+        match format_version {
+            FORMAT_VERSION => {}
+            1 => return Err(format!("Binary fingerprint format 1 holds scheme 1 hashes; re-fingerprint the audio with scheme {}", FINGERPRINT_SCHEME_VERSION).into()),
+            _ => return Err(format!("Unsupported fingerprint format version {}", format_version).into()),
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err("Fingerprint data is truncated".into());
+        }
+        let scheme_version = u32::from_le_bytes(bytes[8..12].try_into()?);
+        let scheme = FingerprintScheme::from_code(u32::from_le_bytes(bytes[12..16].try_into()?))?;
+        let duration = f64::from_le_bytes(bytes[16..24].try_into()?);
+        let count = u32::from_le_bytes(bytes[24..HEADER_LEN].try_into()?) as usize;
+        // Every hash takes its 8-byte key and at least one byte of frame delta
+        if count > (bytes.len() - HEADER_LEN) / 9 {
+            return Err("Fingerprint data is truncated".into());
+        }
+
+        let mut hashes = Vec::with_capacity(count);
+        let mut anchor_times = Vec::with_capacity(count);
+        let mut position = HEADER_LEN;
+        let mut anchor_frame = 0u32;
+        for _ in 0..count {
+            let hash_bytes = bytes.get(position..position + 8).ok_or("Fingerprint data is truncated")?;
+            position += 8;
+            let frame_delta = u32::try_from(read_varint(bytes, &mut position)?)?;
+            anchor_frame = anchor_frame.checked_add(frame_delta).ok_or("Fingerprint anchor times overflow")?;
+
+            hashes.push(u64::from_le_bytes(hash_bytes.try_into()?));
+            anchor_times.push(frame_to_time(anchor_frame));
+        }
+
+        Ok(Self { scheme_version, scheme, duration, hashes, anchor_times })
+    }
+
+    // Accepts either serialization, telling them apart by the binary magic
+    pub fn decode(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if data.starts_with(MAGIC) {
+            Self::from_bytes(data)
+        } else {
+            Self::from_json(std::str::from_utf8(data)?)
+        }
+    }
+}
+
+// Peak frequency bins of every STFT frame, the input to hashing. Exposed so the peak
+// picking can be checked on its own, e.g. by the golden fingerprint tests.
+pub fn spectral_peaks(samples: &[f64], sample_rate: f64) -> Result<Vec<Vec<usize>>, Box<dyn std::error::Error>> {
+    peaks(resample(samples, sample_rate)?)
+}
+
+fn resample(samples: &[f64], sample_rate: f64) -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
+    if sample_rate == OUTPUT_SAMPLE_RATE {
+        return Ok(vec![samples.to_vec()]);
+    }
+    audio_processing::resample_to_output(samples.to_vec(), sample_rate)
+}
+
+fn peaks(audio: Vec<Vec<f64>>) -> Result<Vec<Vec<usize>>, Box<dyn std::error::Error>> {
+    let spectr: Vec<Vec<f64>> = make_spectr::window_audio(audio)?;
+    process_spectr::find_spectral_peaks(&spectr)
+}
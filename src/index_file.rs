@@ -0,0 +1,490 @@
+use memmap2::Mmap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::constants::*;
+use crate::fingerprint::{FingerprintScheme, SchemeParameters};
+use crate::fingerprint_store::*;
+
+// On-disk inverted index, memory-mapped for lookups. Little-endian layout:
+//
+//   header     MAGIC, format version (u32), scheme version (u32), hashing scheme (u32, see
+//              `FingerprintScheme::code`), padding (u32), then u64 song count, hash count,
+//              posting count, and the byte offsets of the three sections below
+//   songs      per song: varint id, varint fingerprint count, then its SongMetadata as a
+//              varint-length-prefixed JSON string
+//   hash table one 24-byte entry per distinct hash, sorted by hash:
+//              hash (u64), postings byte offset (u64), posting count (u32), song count (u32)
+//   postings   per hash, (song_id, anchor_frame) pairs sorted by song then frame, as varints:
+//              song id delta, then the frame delta within the same song or the absolute frame
+//              for a new song
+//
+// anchor_frame is the anchor time in samples at OUTPUT_SAMPLE_RATE, which is exact for the
+// times produced by `create_pairs`.
+const MAGIC: &[u8; 8] = b"SHZIDX\0\0";
+const FORMAT_VERSION: u32 = 4;
+const HEADER_LEN: usize = 72;
+const TABLE_ENTRY_LEN: usize = 24;
+
+pub(crate) fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+pub(crate) fn read_varint(buffer: &[u8], position: &mut usize) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *buffer.get(*position).ok_or("Index file is truncated")?;
+        *position += 1;
+        if shift > 63 {
+            return Err("Index file contains an invalid varint".into());
+        }
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+// The `len` bytes at `offset`, or an error when the buffer ends before them
+fn read_bytes(buffer: &[u8], offset: usize, len: usize) -> Result<&[u8], Box<dyn std::error::Error>> {
+    offset.checked_add(len)
+        .and_then(|end| buffer.get(offset..end))
+        .ok_or_else(|| "Index file is truncated".into())
+}
+
+fn read_string(buffer: &[u8], position: &mut usize) -> Result<String, Box<dyn std::error::Error>> {
+    let len = usize::try_from(read_varint(buffer, position)?)?;
+    let bytes = read_bytes(buffer, *position, len)?;
+    *position += len;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Result<u32, Box<dyn std::error::Error>> {
+    Ok(u32::from_le_bytes(read_bytes(buffer, offset, 4)?.try_into()?))
+}
+
+fn read_u64(buffer: &[u8], offset: usize) -> Result<u64, Box<dyn std::error::Error>> {
+    Ok(u64::from_le_bytes(read_bytes(buffer, offset, 8)?.try_into()?))
+}
+
+fn read_offset(buffer: &[u8], offset: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(usize::try_from(read_u64(buffer, offset)?)?)
+}
+
+fn corrupt_error() -> Box<dyn std::error::Error> {
+    "Index file is corrupt".into()
+}
+
+pub(crate) fn time_to_frame(anchor_time: f64) -> u32 {
+    (anchor_time * OUTPUT_SAMPLE_RATE).round().max(0.0) as u32
+}
+
+pub(crate) fn frame_to_time(anchor_frame: u32) -> f64 {
+    anchor_frame as f64 / OUTPUT_SAMPLE_RATE
+}
+
+// Collects songs and their `create_pairs` output in memory, then writes the index file
+#[derive(Default)]
+pub struct IndexBuilder {
+    scheme: FingerprintScheme,
+    songs: BTreeMap<u64, SongMetadata>,
+    // hash_key -> [(song_id, anchor_frame)]
+    postings: HashMap<u64, Vec<(u64, u32)>>,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Hashing scheme of the fingerprints added, recorded in the header; pairs by default
+    pub fn with_scheme(mut self, scheme: FingerprintScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    // Copies every song and fingerprint out of an existing store
+    pub fn from_store(store: &dyn FingerprintStore) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut builder = Self::new();
+        if let Some(parameters) = store.scheme_parameters()? {
+            parameters.check_compatible(&SchemeParameters::current(parameters.hashing))?;
+            builder = builder.with_scheme(parameters.hashing);
+        }
+        for song in store.all_songs_hashes()? {
+            let (song, keys, values) = song?;
+            builder.add_song(song.id, &song.metadata, &keys, &values)?;
+        }
+        Ok(builder)
+    }
+
+    pub fn add_song(&mut self, song_id: u64, metadata: &SongMetadata, keys: &[u64], values: &[f64]) -> Result<(), Box<dyn std::error::Error>> {
+        if keys.len() != values.len() {
+            return Err("Keys and values vectors must have the same length".into());
+        }
+        if self.songs.insert(song_id, metadata.clone()).is_some() {
+            return Err(format!("Song id {} was added to the index twice", song_id).into());
+        }
+
+        for (&key, &value) in keys.iter().zip(values) {
+            self.postings.entry(key).or_default().push((song_id, time_to_frame(value)));
+        }
+        Ok(())
+    }
+
+    // Writes the index and returns its size in bytes
+    pub fn write(&self, path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut fingerprint_counts: HashMap<u64, u64> = HashMap::new();
+        for postings in self.postings.values() {
+            for &(song_id, _) in postings {
+                *fingerprint_counts.entry(song_id).or_default() += 1;
+            }
+        }
+
+        let mut songs_section: Vec<u8> = Vec::new();
+        for (&song_id, metadata) in &self.songs {
+            write_varint(&mut songs_section, song_id);
+            write_varint(&mut songs_section, fingerprint_counts.get(&song_id).copied().unwrap_or(0));
+            write_string(&mut songs_section, &serde_json::to_string(metadata)?);
+        }
+
+        let mut hashes: Vec<u64> = self.postings.keys().copied().collect();
+        hashes.sort_unstable();
+
+        let mut table_section: Vec<u8> = Vec::with_capacity(hashes.len() * TABLE_ENTRY_LEN);
+        let mut postings_section: Vec<u8> = Vec::new();
+        let mut posting_count: u64 = 0;
+
+        for hash in &hashes {
+            let mut postings = self.postings[hash].clone();
+            postings.sort_unstable();
+            let mut song_ids: Vec<u64> = postings.iter().map(|&(song_id, _)| song_id).collect();
+            song_ids.dedup();
+
+            table_section.extend_from_slice(&hash.to_le_bytes());
+            table_section.extend_from_slice(&(postings_section.len() as u64).to_le_bytes());
+            table_section.extend_from_slice(&(postings.len() as u32).to_le_bytes());
+            table_section.extend_from_slice(&(song_ids.len() as u32).to_le_bytes());
+
+            let (mut previous_song, mut previous_frame) = (0u64, 0u32);
+            for &(song_id, anchor_frame) in &postings {
+                let song_delta = song_id - previous_song;
+                write_varint(&mut postings_section, song_delta);
+                if song_delta == 0 {
+                    write_varint(&mut postings_section, (anchor_frame - previous_frame) as u64);
+                } else {
+                    write_varint(&mut postings_section, anchor_frame as u64);
+                }
+                previous_song = song_id;
+                previous_frame = anchor_frame;
+            }
+            posting_count += postings.len() as u64;
+        }
+
+        let songs_offset = HEADER_LEN as u64;
+        let table_offset = songs_offset + songs_section.len() as u64;
+        let postings_offset = table_offset + table_section.len() as u64;
+
+        let mut header: Vec<u8> = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&FINGERPRINT_SCHEME_VERSION.to_le_bytes());
+        header.extend_from_slice(&self.scheme.code().to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        for value in [self.songs.len() as u64, hashes.len() as u64, posting_count, songs_offset, table_offset, postings_offset] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&header)?;
+        writer.write_all(&songs_section)?;
+        writer.write_all(&table_section)?;
+        writer.write_all(&postings_section)?;
+        writer.flush()?;
+
+        Ok(postings_offset + postings_section.len() as u64)
+    }
+}
+
+// One entry of the hash table
+struct TableEntry {
+    hash: u64,
+    postings_start: usize,
+    posting_count: usize,
+    song_count: u64,
+}
+
+// Read-only fingerprint store backed by a memory-mapped index file
+pub struct IndexFile {
+    mmap: Mmap,
+    scheme: FingerprintScheme,
+    hash_count: usize,
+    table_offset: usize,
+    postings_offset: usize,
+    // song id -> (metadata, fingerprint count)
+    songs: BTreeMap<u64, (SongMetadata, u64)>,
+}
+
+impl IndexFile {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        // Safety: the index is written once by IndexBuilder and never modified in place
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(format!("{} is not a fingerprint index file", path).into());
+        }
+        let format_version = read_u32(&mmap, 8)?;
+        if format_version != FORMAT_VERSION {
+            return Err(format!("Unsupported index format version {}", format_version).into());
+        }
+        let scheme_version = read_u32(&mmap, 12)?;
+        if scheme_version != FINGERPRINT_SCHEME_VERSION {
+            return Err(format!("Index was built with fingerprint scheme {}, this build uses {}", scheme_version, FINGERPRINT_SCHEME_VERSION).into());
+        }
+
+        let scheme = FingerprintScheme::from_code(read_u32(&mmap, 16)?)?;
+
+        let song_count = read_u64(&mmap, 24)?;
+        let hash_count = read_offset(&mmap, 32)?;
+        let songs_offset = read_offset(&mmap, 48)?;
+        let table_offset = read_offset(&mmap, 56)?;
+        let postings_offset = read_offset(&mmap, 64)?;
+
+        // The sections follow the header in order, and the hash table fills the space between
+        // its offset and the postings'
+        let table_end = hash_count.checked_mul(TABLE_ENTRY_LEN).and_then(|len| len.checked_add(table_offset));
+        if songs_offset < HEADER_LEN || table_offset < songs_offset || table_end != Some(postings_offset) {
+            return Err(corrupt_error());
+        }
+        if postings_offset > mmap.len() {
+            return Err("Index file is truncated".into());
+        }
+
+        let songs_section = &mmap[..table_offset];
+        let mut songs = BTreeMap::new();
+        let mut position = songs_offset;
+        for _ in 0..song_count {
+            let song_id = read_varint(songs_section, &mut position)?;
+            let fingerprint_count = read_varint(songs_section, &mut position)?;
+            let metadata = serde_json::from_str(&read_string(songs_section, &mut position)?)?;
+            songs.insert(song_id, (metadata, fingerprint_count));
+        }
+
+        Ok(Self { mmap, scheme, hash_count, table_offset, postings_offset, songs })
+    }
+
+    // `index` is below `hash_count`, whose entries `open` checked are inside the file
+    fn table_entry(&self, index: usize) -> Result<TableEntry, Box<dyn std::error::Error>> {
+        let offset = self.table_offset + index * TABLE_ENTRY_LEN;
+        Ok(TableEntry {
+            hash: read_u64(&self.mmap, offset)?,
+            postings_start: read_offset(&self.mmap, offset + 8)?,
+            posting_count: read_u32(&self.mmap, offset + 16)? as usize,
+            song_count: read_u32(&self.mmap, offset + 20)? as u64,
+        })
+    }
+
+    // Binary search over the sorted hash table
+    fn find_hash(&self, hash: u64) -> Result<Option<TableEntry>, Box<dyn std::error::Error>> {
+        let (mut low, mut high) = (0, self.hash_count);
+        while low < high {
+            let middle = (low + high) / 2;
+            let entry = self.table_entry(middle)?;
+            if entry.hash == hash {
+                return Ok(Some(entry));
+            } else if entry.hash < hash {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_postings(&self, entry: &TableEntry) -> Result<Vec<(u64, u32)>, Box<dyn std::error::Error>> {
+        let postings = &self.mmap[self.postings_offset..];
+        // Every posting takes at least two bytes
+        if entry.posting_count > postings.len().saturating_sub(entry.postings_start) / 2 {
+            return Err("Index file is truncated".into());
+        }
+        let mut position = entry.postings_start;
+        let mut result = Vec::with_capacity(entry.posting_count);
+
+        let (mut song_id, mut anchor_frame) = (0u64, 0u32);
+        for _ in 0..entry.posting_count {
+            let song_delta = read_varint(postings, &mut position)?;
+            let frame_value = u32::try_from(read_varint(postings, &mut position)?)?;
+            song_id = song_id.checked_add(song_delta).ok_or_else(corrupt_error)?;
+            anchor_frame = match song_delta {
+                0 => anchor_frame.checked_add(frame_value).ok_or_else(corrupt_error)?,
+                _ => frame_value,
+            };
+            result.push((song_id, anchor_frame));
+        }
+        Ok(result)
+    }
+}
+
+fn read_only_error() -> Box<dyn std::error::Error> {
+    "Index files are read-only; rebuild them with IndexBuilder".into()
+}
+
+impl FingerprintStore for IndexFile {
+    fn register_song(&self, _metadata: &SongMetadata) -> Result<u64, Box<dyn std::error::Error>> {
+        Err(read_only_error())
+    }
+
+    fn insert_hashes(&self, _song_id: u64, _keys: &[u64], _values: &[f64]) -> Result<usize, Box<dyn std::error::Error>> {
+        Err(read_only_error())
+    }
+
+    fn insert_stopped_hashes(&self, _song_id: u64, _keys: &[u64]) -> Result<(), Box<dyn std::error::Error>> {
+        Err(read_only_error())
+    }
+
+    fn lookup_hashes(&self, keys: &[u64]) -> Result<Vec<FingerprintRow>, Box<dyn std::error::Error>> {
+        let mut unique_keys = keys.to_vec();
+        unique_keys.sort_unstable();
+        unique_keys.dedup();
+
+        let mut result: Vec<FingerprintRow> = Vec::new();
+        for key in unique_keys {
+            if let Some(entry) = self.find_hash(key)? {
+                for (song_id, anchor_frame) in self.read_postings(&entry)? {
+                    result.push((key, song_id, frame_to_time(anchor_frame)));
+                }
+            }
+        }
+
+        result.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        Ok(result)
+    }
+
+    fn song_info(&self, song_id: u64) -> Result<SongInfo, Box<dyn std::error::Error>> {
+        match self.songs.get(&song_id) {
+            Some((metadata, _)) => Ok(SongInfo { id: song_id, metadata: metadata.clone() }),
+            None => Err("Song not found".into()),
+        }
+    }
+
+    fn delete_song(&self, _song_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+        Err(read_only_error())
+    }
+
+    fn list_songs(&self) -> Result<Vec<SongInfo>, Box<dyn std::error::Error>> {
+        Ok(self.songs.iter().map(|(&id, (metadata, _))| SongInfo { id, metadata: metadata.clone() }).collect())
+    }
+
+    // The index is organised by hash, so this scans every posting list; use
+    // `all_songs_hashes` to read many songs
+    fn song_hashes(&self, song_id: u64) -> Result<(Vec<u64>, Vec<f64>), Box<dyn std::error::Error>> {
+        if !self.songs.contains_key(&song_id) {
+            return Err("Song not found".into());
+        }
+
+        let mut rows: Vec<(f64, u64)> = Vec::new();
+        for index in 0..self.hash_count {
+            let entry = self.table_entry(index)?;
+            for (posting_song, anchor_frame) in self.read_postings(&entry)? {
+                if posting_song == song_id {
+                    rows.push((frame_to_time(anchor_frame), entry.hash));
+                }
+            }
+        }
+
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(rows.into_iter().map(|(time, hash)| (hash, time)).unzip())
+    }
+
+    // One pass over the postings, grouped by song: (anchor_frame, hash table index) per
+    // posting, which sort like `song_hashes` orders them
+    fn all_songs_hashes(&self) -> Result<SongHashesIter<'_>, Box<dyn std::error::Error>> {
+        let mut by_song: HashMap<u64, Vec<(u32, usize)>> = self.songs.iter()
+            .map(|(&id, &(_, fingerprint_count))| (id, Vec::with_capacity(usize::try_from(fingerprint_count).unwrap_or(0))))
+            .collect();
+        for index in 0..self.hash_count {
+            let entry = self.table_entry(index)?;
+            for (song_id, anchor_frame) in self.read_postings(&entry)? {
+                by_song.get_mut(&song_id).ok_or_else(corrupt_error)?.push((anchor_frame, index));
+            }
+        }
+
+        Ok(Box::new(self.songs.iter().map(move |(&id, (metadata, _))| {
+            let mut postings = by_song.remove(&id).unwrap_or_default();
+            postings.sort_unstable();
+            let mut keys = Vec::with_capacity(postings.len());
+            let mut values = Vec::with_capacity(postings.len());
+            for (anchor_frame, index) in postings {
+                keys.push(self.table_entry(index)?.hash);
+                values.push(frame_to_time(anchor_frame));
+            }
+            Ok((SongInfo { id, metadata: metadata.clone() }, keys, values))
+        })))
+    }
+
+    fn song_count(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self.songs.len() as u64)
+    }
+
+    fn hash_song_counts(&self, keys: &[u64]) -> Result<HashMap<u64, u64>, Box<dyn std::error::Error>> {
+        let mut result: HashMap<u64, u64> = HashMap::new();
+        for &key in keys {
+            if result.contains_key(&key) {
+                continue;
+            }
+            if let Some(entry) = self.find_hash(key)? {
+                result.insert(key, entry.song_count);
+            }
+        }
+        Ok(result)
+    }
+
+    fn fingerprint_counts(&self) -> Result<Vec<(u64, u64)>, Box<dyn std::error::Error>> {
+        Ok(self.songs.iter().map(|(&id, &(_, fingerprint_count))| (id, fingerprint_count)).collect())
+    }
+
+    // Reads the counts in the hash table without decoding any postings, keeping the `limit`
+    // most frequent in a heap
+    fn most_frequent_hashes(&self, limit: usize) -> Result<Vec<HashFrequency>, Box<dyn std::error::Error>> {
+        // The heap's top is the least frequent hash kept
+        let mut top: BinaryHeap<(Reverse<u64>, u64, u64)> = BinaryHeap::with_capacity(limit + 1);
+        for index in 0..self.hash_count {
+            let entry = self.table_entry(index)?;
+            top.push((Reverse(entry.song_count), entry.hash, entry.posting_count as u64));
+            if top.len() > limit {
+                top.pop();
+            }
+        }
+
+        Ok(top.into_sorted_vec()
+            .into_iter()
+            .map(|(Reverse(songs), hash, occurrences)| HashFrequency { hash, occurrences, songs })
+            .collect())
+    }
+
+    fn storage_size(&self) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        Ok(Some(self.mmap.len() as u64))
+    }
+
+    // `open` only accepts indexes of this build's scheme version
+    fn scheme_parameters(&self) -> Result<Option<SchemeParameters>, Box<dyn std::error::Error>> {
+        Ok(Some(SchemeParameters::current(self.scheme)))
+    }
+
+    fn record_scheme_parameters(&self, _parameters: &SchemeParameters) -> Result<(), Box<dyn std::error::Error>> {
+        Err(read_only_error())
+    }
+}
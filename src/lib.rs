@@ -2,74 +2,184 @@ mod audio_processing;
 mod make_spectr;
 mod process_spectr;
 mod create_hash;
-mod database_interaction;
+mod create_triplets;
+pub mod database_interaction;
 mod constants;
 mod match_song;
+pub mod engine;
+pub mod fingerprint_store;
+pub mod memory_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod index_file;
+pub mod catalogue_transfer;
+pub mod fingerprint;
+pub mod catalogue_stats;
+pub mod stop_list;
+pub mod batch_ingest;
+pub mod evaluation;
+pub mod segmentation;
+pub mod degradation;
+pub mod pipeline;
+pub mod calibration;
+mod debug_log;
+
+use std::sync::OnceLock;
+use serde::Serialize;
+use engine::{Recognition, ShazamEngine};
+use fingerprint::Fingerprint;
+use fingerprint_store::{SongInfo, SongMetadata};
 
 #[repr(C)]
 pub struct ShazamResult {
     pub name: *mut u8,
-    pub artist: *mut u8
+    pub artist: *mut u8,
+    // JSON of the full SongInfo (id and all metadata), plus `confidence`, the clip's `start`
+    // and `end` within the song in seconds and its `tempo_factor` when recognizing; empty on error
+    pub metadata: *mut u8
+}
+
+// A stored song, or a recognized one together with where the clip lies in it
+#[derive(Serialize)]
+#[serde(untagged)]
+enum SongResult {
+    Ingested(SongInfo),
+    Recognized(Recognition),
 }
 
+impl SongResult {
+    fn song(self) -> SongInfo {
+        match self {
+            SongResult::Ingested(song) => song,
+            SongResult::Recognized(recognition) => recognition.song,
+        }
+    }
+}
+
+/// # Safety
+/// `song_name` and `artist_name` must point to `song_name_len` and `artist_name_len` bytes of valid UTF-8.
 #[unsafe(no_mangle)]
-pub extern fn run_shazam(song_name: *const u8, song_name_len: usize, artist_name: *const u8, artist_name_len: usize, to_recognize: bool) -> ShazamResult {
+pub unsafe extern "C" fn run_shazam(song_name: *const u8, song_name_len: usize, artist_name: *const u8, artist_name_len: usize, to_recognize: bool) -> ShazamResult {
     let song_name = unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(song_name, song_name_len)) };
     let artist_name = unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(artist_name, artist_name_len)) };
     
-    let (name, artist) = match run_shazam_internal(song_name, artist_name, to_recognize) {
-        Ok((n, a)) => (n, a),
-        Err(e) => (e.to_string(), String::new())
+    to_shazam_result(run_shazam_internal(&SongMetadata::new(song_name, artist_name), to_recognize))
+}
+
+/// # Safety
+/// `metadata_json` must point to `metadata_json_len` bytes holding a JSON `SongMetadata`
+/// object. It is only read when ingesting.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn run_shazam_with_metadata(metadata_json: *const u8, metadata_json_len: usize, to_recognize: bool) -> ShazamResult {
+    let metadata_json = unsafe { std::slice::from_raw_parts(metadata_json, metadata_json_len) };
+
+    let result = if to_recognize {
+        run_shazam_internal(&SongMetadata::default(), true)
+    } else {
+        match serde_json::from_slice::<SongMetadata>(metadata_json) {
+            Ok(metadata) => run_shazam_internal(&metadata, false),
+            Err(e) => Err(format!("Invalid song metadata: {}", e).into()),
+        }
+    };
+    to_shazam_result(result)
+}
+
+/// # Safety
+/// `fingerprint` must point to `fingerprint_len` readable bytes holding a binary or JSON
+/// `Fingerprint`; `song_name` and `artist_name` as for `run_shazam`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn run_shazam_fingerprint(fingerprint: *const u8, fingerprint_len: usize, song_name: *const u8, song_name_len: usize, artist_name: *const u8, artist_name_len: usize, to_recognize: bool) -> ShazamResult {
+    let fingerprint = unsafe { std::slice::from_raw_parts(fingerprint, fingerprint_len) };
+    let song_name = unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(song_name, song_name_len)) };
+    let artist_name = unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(artist_name, artist_name_len)) };
+
+    to_shazam_result(run_shazam_fingerprint_internal(fingerprint, &SongMetadata::new(song_name, artist_name), to_recognize))
+}
+
+/// Returns catalogue statistics as JSON in `metadata`, listing the `top_hashes` most
+/// widespread hashes. On error `metadata` is empty and `name` holds the message.
+#[unsafe(no_mangle)]
+pub extern "C" fn shazam_catalogue_stats(top_hashes: usize) -> ShazamResult {
+    let (error, stats) = match engine().and_then(|engine| engine.stats(top_hashes)) {
+        Ok(stats) => (String::new(), serde_json::to_string(&stats).unwrap_or_default()),
+        Err(e) => (e.to_string(), String::new()),
+    };
+
+    ShazamResult {
+        name: std::ffi::CString::new(error).unwrap().into_raw() as *mut u8,
+        artist: std::ffi::CString::default().into_raw() as *mut u8,
+        metadata: std::ffi::CString::new(stats).unwrap().into_raw() as *mut u8
+    }
+}
+
+fn to_shazam_result(result: Result<SongResult, Box<dyn std::error::Error>>) -> ShazamResult {
+    let (name, artist, metadata) = match result {
+        Ok(result) => {
+            let metadata = serde_json::to_string(&result).unwrap_or_default();
+            let song = result.song();
+            (song.metadata.name, song.metadata.artist, metadata)
+        }
+        Err(e) => (e.to_string(), String::new(), String::new())
     };
     
     let name_cstring = std::ffi::CString::new(name).unwrap();
     let artist_cstring = std::ffi::CString::new(artist).unwrap();
+    let metadata_cstring = std::ffi::CString::new(metadata).unwrap();
     ShazamResult {
         name: name_cstring.into_raw() as *mut u8,
-        artist: artist_cstring.into_raw() as *mut u8
+        artist: artist_cstring.into_raw() as *mut u8,
+        metadata: metadata_cstring.into_raw() as *mut u8
     }
 }
 
-fn run_shazam_internal(song_name: &str, artist_name: &str, to_recognize: bool) -> Result<(String, String), Box<dyn std::error::Error>> {
-    let mut conn = database_interaction::set_up_connection()?;
+// Created on first use and shared by every later call
+static ENGINE: OnceLock<ShazamEngine> = OnceLock::new();
 
-    // database_interaction::clear_database(&mut conn)?;
+fn engine() -> Result<&'static ShazamEngine, Box<dyn std::error::Error>> {
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+    // Keep the pipeline's progress messages on the server console unless RUST_LOG says otherwise
+    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("shazam=debug")).try_init();
+
+    // A failed start-up is not cached, so the next call retries
+    let engine = ShazamEngine::from_env()?;
+    Ok(ENGINE.get_or_init(|| engine))
+}
+
+fn run_shazam_internal(metadata: &SongMetadata, to_recognize: bool) -> Result<SongResult, Box<dyn std::error::Error>> {
+    let engine = engine()?;
 
-    let mut song_id: u64 = 0;
     if !to_recognize {
-        song_id = database_interaction::insert_song(&mut conn, song_name, artist_name)?;
-    }
-    let audio: Vec<Vec<f64>>;
-    if to_recognize {
-        audio = audio_processing::process_audio("audio_to_recognize.wav")?;
+        let id = engine.ingest(metadata, "song_to_process.wav")?;
+        Ok(SongResult::Ingested(SongInfo { id, metadata: metadata.clone() }))
     } else {
-        audio = audio_processing::process_audio("song_to_process.wav")?;
+        Ok(SongResult::Recognized(engine.recognize_audio("audio_to_recognize.wav")?))
     }
-    let spectr: Vec<Vec<f64>> = make_spectr::window_audio(audio)?;
-    let peaks: Vec<Vec<usize>> = process_spectr::find_spectral_peaks(&spectr)?;
-
+}
 
-    let matched_song_id = create_hash::create_pairs(&peaks, song_id, to_recognize, &mut conn)?;
+fn run_shazam_fingerprint_internal(data: &[u8], metadata: &SongMetadata, to_recognize: bool) -> Result<SongResult, Box<dyn std::error::Error>> {
+    let engine = engine()?;
+    let fingerprint = Fingerprint::decode(data)?;
 
-    let result_message = if !to_recognize {
-        (String::from(song_name), String::from(artist_name))
+    if !to_recognize {
+        let id = engine.ingest_fingerprint(metadata, &fingerprint)?;
+        Ok(SongResult::Ingested(SongInfo { id, metadata: metadata.clone() }))
     } else {
-        let (_, matched_name, matched_artist) = database_interaction::get_song_info(&mut conn, matched_song_id)?;
-        (matched_name, matched_artist)
-    };
-
-    Ok(result_message)
+        Ok(SongResult::Recognized(engine.recognize_match(&fingerprint)?))
+    }
 }
 
 
+/// # Safety
+/// Every pointer must be null or come from a `ShazamResult` returned by this library, and be freed only once.
 #[unsafe(no_mangle)]
-pub extern fn free_rust_strings(name_ptr: *mut u8, artist_ptr: *mut u8) {
+pub unsafe extern "C" fn free_rust_strings(name_ptr: *mut u8, artist_ptr: *mut u8, metadata_ptr: *mut u8) {
     unsafe {
-        if !name_ptr.is_null() {
-            let _ = std::ffi::CString::from_raw(name_ptr as *mut std::os::raw::c_char);
-        }
-        if !artist_ptr.is_null() {
-            let _ = std::ffi::CString::from_raw(artist_ptr as *mut std::os::raw::c_char);
+        for ptr in [name_ptr, artist_ptr, metadata_ptr] {
+            if !ptr.is_null() {
+                let _ = std::ffi::CString::from_raw(ptr as *mut std::os::raw::c_char);
+            }
         }
     }
-}
\ No newline at end of file
+}
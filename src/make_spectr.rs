@@ -9,6 +9,7 @@ use std::fs::File;
 use std::io::{Write, BufWriter};
 use std::f64::consts::LOG10_E;
 use crate::constants::*;
+use crate::debug_log::create_log;
 
 
 pub fn window_function(frame : &[f64]) -> Vec<f64> {
@@ -17,7 +18,7 @@ pub fn window_function(frame : &[f64]) -> Vec<f64> {
     for n in 0..frame_length {
         windowed_frame[n] = frame[n] * 0.5 * (1.0 - (2.0 * PI * n as f64 / (frame_length as f64 - 1.0)).cos());
     }
-    return windowed_frame;
+    windowed_frame
 }
 
 pub fn compute_fft(frame: &[f64]) -> Vec<f64> {
@@ -56,7 +57,7 @@ pub fn compute_fft(frame: &[f64]) -> Vec<f64> {
 pub fn window_audio(audio : Vec<Vec<f64>>) -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
 
     // let audio: Vec<Vec<f64>> = audio_processing::process_audio()?;
-    let audio: Vec<f64> = audio[0].clone();
+    let audio: Vec<f64> = audio.first().ok_or("No audio channel to window")?.clone();
 
     
 
@@ -69,7 +70,10 @@ pub fn window_audio(audio : Vec<Vec<f64>>) -> Result<Vec<Vec<f64>>, Box<dyn std:
 
     let mut padded_audio = audio.to_vec();
     padded_audio.extend(vec![0.0; padding_needed]);
-    println!("Audio size: {} samples ({:.2} seconds)", 
+    if padded_audio.len() < frame_length {
+        return Err("Audio is shorter than one frame".into());
+    }
+    log::debug!("Audio size: {} samples ({:.2} seconds)", 
              padded_audio.len(), 
              padded_audio.len() as f64 / OUTPUT_SAMPLE_RATE);
     let mut spectrogram: Vec<Vec<f64>> = Vec::with_capacity((padded_audio.len() - frame_length) / hop_length + 1);
@@ -84,13 +88,13 @@ pub fn window_audio(audio : Vec<Vec<f64>>) -> Result<Vec<Vec<f64>>, Box<dyn std:
 
         spectrogram.push(fft_magnitude);
     }
-    println!("Generated spectrogram with {} time frames.", spectrogram.len());
+    log::debug!("Generated spectrogram with {} time frames.", spectrogram.len());
 
 
-    let file = File::create("../log/log_spectrogram.txt")?;
-    let mut writer = BufWriter::new(file);
-    for row in &spectrogram {
-        writeln!(writer, "{:?}", row)?; // Writes each row as [1, 2, 3]
+    if let Some(mut writer) = create_log("log_spectrogram.txt")? {
+        for row in &spectrogram {
+            writeln!(writer, "{:?}", row)?; // Writes each row as [1, 2, 3]
+        }
     }
 
     // visualize_spectrogram(&spectrogram)?;
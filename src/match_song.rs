@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use crate::calibration::Calibration;
+use crate::constants::{HOP_LENGTH, OUTPUT_SAMPLE_RATE};
 
 struct Song {
     id: u64,
@@ -15,11 +17,11 @@ impl Song {
         }
     }
     
-    fn new_sample(id: u64, keys: &Vec<u64>, values: &Vec<f64>) -> Self {
+    fn new_sample(id: u64, keys: &[u64], values: &[f64]) -> Self {
         Self {
             id,
-            hashes: keys.clone(),
-            anchor_times: values.clone(),
+            hashes: keys.to_vec(),
+            anchor_times: values.to_vec(),
         }
     }
     
@@ -32,20 +34,45 @@ impl Song {
         let mut map: HashMap<u64, Vec<f64>> = HashMap::new();
         
         for (hash, anchor_time) in self.hashes.iter().zip(self.anchor_times.iter()) {
-            map.entry(*hash).or_insert(Vec::new()).push(*anchor_time);
+            map.entry(*hash).or_default().push(*anchor_time);
         }
 
         map
     }
 }
 
+// Inverse document frequency of each hash, from song counts over the whole catalogue
+pub struct HashWeights {
+    catalogue_songs: u64,
+    song_counts: HashMap<u64, u64>,
+}
+
+impl HashWeights {
+    pub fn new(catalogue_songs: u64, song_counts: HashMap<u64, u64>) -> Self {
+        Self { catalogue_songs, song_counts }
+    }
+
+    // 1.0 for a hash carried by a single song, falling towards ln(2) / ln(1 + N) for a
+    // hash carried by all N songs
+    fn weight(&self, hash: u64) -> f64 {
+        let catalogue_songs = self.catalogue_songs.max(1) as f64;
+        let song_count = self.song_counts.get(&hash).copied().unwrap_or(1).clamp(1, self.catalogue_songs.max(1)) as f64;
+        (1.0 + catalogue_songs / song_count).ln() / (1.0 + catalogue_songs).ln()
+    }
+}
+
 // Configuration struct to make parameters adjustable
 pub struct MatchConfig {
     precision_factor: f64,     // Controls time offset precision
     offset_bin_size: i32,      // Size of offset bins for grouping similar offsets
+    offset_window: i64,        // Bins on each side of a bin that count towards its peak
     min_match_threshold: usize, // Minimum matches required to consider a song
-    min_confidence: f64,       // Minimum confidence (%) required for a valid match
+    min_confidence: f64,       // Minimum calibrated confidence (%) required for a valid match
     max_results: usize,        // Maximum number of results to return
+    calibration: Calibration,  // Maps peak statistics to confidence
+    tempo_factors: Vec<f64>,   // Clip-to-song time scales tried, closest to 1 first
+    line_fit_candidates: usize, // Strongest songs whose alignment is refitted as a line
+    max_drift: f64,            // Largest tempo change the line fit allows around the histogram's
 }
 
 impl Default for MatchConfig {
@@ -53,25 +80,330 @@ impl Default for MatchConfig {
         Self {
             precision_factor: 1000.0,
             offset_bin_size: 5,      // Group offsets within 5ms of each other
+            offset_window: 13,       // +-65ms: true matches jitter by one STFT frame (64ms)
             min_match_threshold: 2,  // Lowered to 2 for better sensitivity
-            min_confidence: 1.0,     // Lowered to 1% to include more potential matches
+            min_confidence: 1.0,     // Drop candidates with under a 1% chance of being right
             max_results: 7,          // Show top 7 results
+            calibration: Calibration::default(),
+            tempo_factors: vec![1.0], // Clips play at the song's speed
+            line_fit_candidates: 5,
+            max_drift: 0.02,         // Pair hashes survive a couple of percent of speed change
         }
     }
 }
 
-pub fn match_song(
-    matches: Vec<(u64, u64, f64)>, 
-    keys: &Vec<u64>, 
-    values: &Vec<f64>,
-    config: Option<MatchConfig>
-) -> Result<(f64, u64), Box<dyn std::error::Error>> {
-    
-    let config = config.unwrap_or_default();
+impl MatchConfig {
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    // Refits the alignment of the `candidates` strongest songs as a line through their
+    // (clip time, song time) hits, with a slope up to `max_drift` away from the histogram's
+    // tempo. 0 candidates turns the line fit off.
+    pub fn with_line_fit(mut self, candidates: usize, max_drift: f64) -> Self {
+        self.line_fit_candidates = candidates;
+        self.max_drift = max_drift;
+        self
+    }
+
+    // Also tries clips played up to `max_change` faster or slower than the song, in steps of
+    // `step`. Only useful with tempo-invariant hashes (`FingerprintScheme::Triplets`).
+    pub fn with_tempo_search(mut self, max_change: f64, step: f64) -> Self {
+        self.tempo_factors = slopes_around(1.0, max_change, step).collect();
+        self
+    }
+}
+
+// Votes one full-weight hash adds to an offset bin
+const VOTES_PER_HASH: f64 = 100.0;
+
+// One song's best alignment with the query clip
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCandidate {
+    pub song_id: u64,
+    // Song time at the clip's start in seconds (song time minus clip time scaled by
+    // `tempo_factor`): the weighted mean offset of the hashes in the winning window of the
+    // offset histogram, or on the fitted line (see `MatchConfig::with_line_fit`)
+    pub offset: f64,
+    // Song seconds per clip second: above 1 when the clip plays faster than the song. Comes
+    // from the line fit, and from the tempo grid of `MatchConfig::with_tempo_search` for
+    // songs outside the line fit's candidates.
+    pub tempo_factor: f64,
+    // Weighted standard deviation of those hashes' offsets, in seconds. Small for a clean
+    // alignment; grows when the peak is chance agreement.
+    pub offset_spread: f64,
+    // Where the clip lies within the song, in seconds rounded to whole STFT frames. `start`
+    // is 0 when the clip begins before the song does, and `end` is the song's length once
+    // `clamp_to_song` has seen a clip run past the song's end.
+    pub start: f64,
+    pub end: f64,
+    // Votes of the hashes on the alignment, `VOTES_PER_HASH` per full-weight hash
+    pub weighted_matches: usize,
+    // Weighted hashes in the winning window per hash of the clip: the share of the clip
+    // that agrees on this alignment
+    pub hash_fraction: f64,
+    // Weighted matches over those of the strongest other song, or of the song's best
+    // alignment elsewhere when no other song matched (at least one full-weight hash). Above 1
+    // only for the top candidate.
+    pub margin: f64,
+    // Estimated probability, in percent, that this is the right song, from `hash_fraction`
+    // and `margin` through the configured `Calibration`
+    pub confidence: f64,
+}
+
+// Seconds rounded to the nearest STFT frame (hop), the resolution of anchor times
+impl MatchCandidate {
+    // The matcher only sees the hashes the clip hit, so the song's length comes from the caller
+    pub fn clamp_to_song(&mut self, song_duration: f64) {
+        self.end = self.end.min(song_duration).max(self.start);
+    }
+}
+
+fn to_frame_precision(seconds: f64) -> f64 {
+    let frame = HOP_LENGTH as f64 / OUTPUT_SAMPLE_RATE;
+    (seconds / frame).round() * frame
+}
+
+// Offset histogram of one song: votes per offset bin plus every (offset, weight) hit
+#[derive(Default)]
+struct OffsetHistogram {
+    votes: BTreeMap<i64, usize>,
+    hits: Vec<(f64, f64)>,
+}
+
+// Best alignment found in an `OffsetHistogram`
+struct OffsetPeak {
+    votes: usize,
+    offset: f64,
+    spread: f64,
+    // Votes of the best window clear of the peak's: the song's strongest chance alignment
+    background: usize,
+}
+
+impl OffsetHistogram {
+    // Floor, so that bins have equal width on both sides of zero
+    fn bin(offset: f64, config: &MatchConfig) -> i64 {
+        (offset * config.precision_factor / config.offset_bin_size as f64).floor() as i64
+    }
+
+    fn add(&mut self, offset: f64, weight: f64, config: &MatchConfig) {
+        *self.votes.entry(Self::bin(offset, config)).or_insert(0) += (weight * VOTES_PER_HASH) as usize;
+        self.hits.push((offset, weight));
+    }
+
+    // Slides a window of `2 * offset_window + 1` bins over the histogram and takes the one
+    // with the most votes (the earliest on ties), so a peak split across bin edges is not
+    // undercounted. The offset and spread are the weighted mean and standard deviation of
+    // the hits inside that window.
+    fn peak(&self, config: &MatchConfig) -> Option<OffsetPeak> {
+        let window = config.offset_window;
+        let windows: Vec<(i64, usize)> = self.votes.keys()
+            .map(|&center| (center, self.votes.range(center - window..=center + window).map(|(_, votes)| votes).sum::<usize>()))
+            .collect();
+        let (center, votes) = windows.iter().copied()
+            .fold(None, |best: Option<(i64, usize)>, (center, votes)| match best {
+                Some((_, best_votes)) if best_votes >= votes => best,
+                _ => Some((center, votes)),
+            })?;
+        let background = windows.iter()
+            .filter(|(other, _)| (other - center).abs() > 2 * window)
+            .map(|&(_, votes)| votes)
+            .max()
+            .unwrap_or(0);
+
+        let in_window: Vec<(f64, f64)> = self.hits.iter()
+            .filter(|&&(offset, _)| (Self::bin(offset, config) - center).abs() <= window)
+            .copied()
+            .collect();
+        let total_weight: f64 = in_window.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 {
+            // Only zero-weight hits: fall back to the window's centre
+            let offset = (center as f64 + 0.5) * config.offset_bin_size as f64 / config.precision_factor;
+            return Some(OffsetPeak { votes, offset, spread: 0.0, background });
+        }
+        let offset = in_window.iter().map(|(offset, weight)| offset * weight).sum::<f64>() / total_weight;
+        let variance = in_window.iter().map(|(hit, weight)| weight * (hit - offset).powi(2)).sum::<f64>() / total_weight;
+
+        Some(OffsetPeak { votes, offset, spread: variance.sqrt(), background })
+    }
+}
+
+// Votes of the best `width` adjacent one-frame offset bins for the line `song_time = slope *
+// clip_time + offset`, and the offset at their middle. Bins are centred on whole frames: at
+// a slope of exactly 1 every offset lies on a whole frame, so the finer `OffsetHistogram`
+// windows would collect more chance votes there than at other slopes.
+fn frame_score(hits: &[(f64, f64, f64)], slope: f64, width: usize) -> (usize, f64) {
+    let frame = HOP_LENGTH as f64 / OUTPUT_SAMPLE_RATE;
+    let bins: Vec<i64> = hits.iter()
+        .map(|&(sample_time, song_time, _)| ((song_time - slope * sample_time) / frame).round() as i64)
+        .collect();
+    let (Some(&first), Some(&last)) = (bins.iter().min(), bins.iter().max()) else {
+        return (0, 0.0);
+    };
+
+    // Dense votes from bin `first` on, padded so that every bin starts a full run of `width`
+    let mut votes = vec![0usize; (last - first) as usize + width];
+    for (bin, &(_, _, weight)) in bins.iter().zip(hits) {
+        votes[(bin - first) as usize] += (weight * VOTES_PER_HASH) as usize;
+    }
+    votes.windows(width)
+        .enumerate()
+        .map(|(i, run)| (run.iter().sum::<usize>(), i))
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map_or((0, 0.0), |(score, i)| (score, (first as f64 + i as f64 + (width - 1) as f64 / 2.0) * frame))
+}
+
+// The first of `slopes` with the best `frame_score`, with its offset
+fn best_slope(hits: &[(f64, f64, f64)], slopes: impl IntoIterator<Item = f64>, width: usize) -> Option<(f64, f64)> {
+    let mut best: Option<(usize, f64, f64)> = None;
+    for slope in slopes {
+        let (score, offset) = frame_score(hits, slope, width);
+        if best.is_none_or(|(best_score, _, _)| score > best_score) {
+            best = Some((score, slope, offset));
+        }
+    }
+    best.map(|(_, slope, offset)| (slope, offset))
+}
+
+// `center` and the values up to `max_change` on either side of it in steps of `step`,
+// closest to `center` first
+fn slopes_around(center: f64, max_change: f64, step: f64) -> impl Iterator<Item = f64> {
+    let steps = if step > 0.0 { (max_change / step).round() as i64 } else { 0 };
+    (0..=2 * steps)
+        .map(|n| if n % 2 == 0 { -(n / 2) } else { (n + 1) / 2 })
+        .map(move |n| center + n as f64 * step)
+}
+
+// The tempo factor whose offsets line up best, the one closest to 1 on ties. Factors are
+// compared by the votes in the best two adjacent frames.
+fn best_tempo_factor(hits: &[(f64, f64, f64)], config: &MatchConfig) -> f64 {
+    if config.tempo_factors.len() < 2 {
+        return config.tempo_factors.first().copied().unwrap_or(1.0);
+    }
+    best_slope(hits, config.tempo_factors.iter().copied(), 2).map_or(1.0, |(slope, _)| slope)
+}
+
+// Weighted least-squares line through the hits of the peak's window, for a factor between
+// the grid points of `config.tempo_factors`. Moves the peak's offset onto the line.
+fn refine_tempo_factor(hits: &[(f64, f64, f64)], peak: &mut OffsetPeak, tempo_factor: f64, config: &MatchConfig) -> f64 {
+    let Some(step) = config.tempo_factors.windows(2).map(|pair| (pair[1] - pair[0]).abs()).reduce(f64::min) else {
+        return tempo_factor;
+    };
+    let window = (config.offset_window as f64 + 0.5) * config.offset_bin_size as f64 / config.precision_factor;
+    let inliers: Vec<&(f64, f64, f64)> = hits.iter()
+        .filter(|&&(sample_time, song_time, _)| (song_time - tempo_factor * sample_time - peak.offset).abs() <= window)
+        .collect();
+
+    let total_weight: f64 = inliers.iter().map(|hit| hit.2).sum();
+    if total_weight <= 0.0 {
+        return tempo_factor;
+    }
+    let mean_sample = inliers.iter().map(|hit| hit.2 * hit.0).sum::<f64>() / total_weight;
+    let mean_song = inliers.iter().map(|hit| hit.2 * hit.1).sum::<f64>() / total_weight;
+    let covariance: f64 = inliers.iter().map(|hit| hit.2 * (hit.0 - mean_sample) * (hit.1 - mean_song)).sum();
+    let variance: f64 = inliers.iter().map(|hit| hit.2 * (hit.0 - mean_sample).powi(2)).sum();
+    if variance <= 0.0 {
+        return tempo_factor;
+    }
+
+    let refined = (covariance / variance).clamp(tempo_factor - step, tempo_factor + step);
+    peak.offset = mean_song - refined * mean_sample;
+    refined
+}
+
+// Most slopes the line fit tries on either side of the histogram's tempo
+const MAX_LINE_FIT_STEPS: f64 = 50.0;
+const LINE_FIT_ITERATIONS: usize = 5;
+// Votes a slope must gain over the histogram's tempo to replace it, in standard deviations
+// of the tempo's hash count: over a clip too short for drift to show, neighbouring slopes
+// score alike and the best of them wins by chance
+const LINE_FIT_SIGNIFICANCE: f64 = 2.0;
+
+// Best alignment of a song's hits with the clip played `tempo_factor` times as fast
+fn peak_at(hits: &[(f64, f64, f64)], tempo_factor: f64, config: &MatchConfig) -> Option<OffsetPeak> {
+    let mut histogram = OffsetHistogram::default();
+    for &(sample_time, song_time, weight) in hits {
+        histogram.add(song_time - tempo_factor * sample_time, weight, config);
+    }
+    histogram.peak(config)
+}
+
+// Fits the line `song_time = tempo_factor * clip_time + offset` through a song's hits, so a
+// clip whose speed differs slightly from the song's, and whose offsets therefore drift
+// across the histogram bins, is scored on all of its hashes. A Hough-style search over
+// slopes within `max_drift` of `tempo_factor` (in steps that move the clip's end by less
+// than the histogram window) finds the line, and a weighted least-squares fit through the
+// hits near it refines it. Returns the peak of the hits near that line, keeping
+// `background` from `peak`, or None when no slope clearly beats `tempo_factor`.
+fn fit_line(hits: &[(f64, f64, f64)], peak: &OffsetPeak, tempo_factor: f64, clip_duration: f64, config: &MatchConfig) -> Option<(OffsetPeak, f64)> {
+    let window = (config.offset_window as f64 + 0.5) * config.offset_bin_size as f64 / config.precision_factor;
+    // Three frames hold the same amount of chance at any slope, plus true matches jittering
+    // by a frame either way
+    let tolerance = 1.5 * HOP_LENGTH as f64 / OUTPUT_SAMPLE_RATE;
+    let step = (window / clip_duration.max(window)).max(config.max_drift / MAX_LINE_FIT_STEPS);
+    // The line runs through the histogram's peak somewhere along the clip, so its hits lie
+    // within the drift over the whole clip of the peak's offset
+    let reach = config.max_drift * clip_duration + tolerance;
+    let hits: Vec<(f64, f64, f64)> = hits.iter()
+        .filter(|hit| (hit.1 - tempo_factor * hit.0 - peak.offset).abs() <= reach)
+        .copied()
+        .collect();
+    let (search_slope, mut offset) = best_slope(&hits, slopes_around(tempo_factor, config.max_drift, step), 3)?;
+    let center_votes = frame_score(&hits, tempo_factor, 3).0 as f64;
+    let gain = frame_score(&hits, search_slope, 3).0 as f64 - center_votes;
+    if gain < LINE_FIT_SIGNIFICANCE * (center_votes * VOTES_PER_HASH).sqrt() {
+        return None;
+    }
+
+    // Least squares through the hits near the line, a few times over as the hits near it change
+    let mut slope = search_slope;
+    for _ in 0..LINE_FIT_ITERATIONS {
+        let inliers: Vec<&(f64, f64, f64)> = hits.iter().filter(|hit| (hit.1 - slope * hit.0 - offset).abs() <= tolerance).collect();
+        let total_weight: f64 = inliers.iter().map(|hit| hit.2).sum();
+        if total_weight <= 0.0 {
+            break;
+        }
+        let mean_sample = inliers.iter().map(|hit| hit.2 * hit.0).sum::<f64>() / total_weight;
+        let mean_song = inliers.iter().map(|hit| hit.2 * hit.1).sum::<f64>() / total_weight;
+        let covariance: f64 = inliers.iter().map(|hit| hit.2 * (hit.0 - mean_sample) * (hit.1 - mean_song)).sum();
+        let variance: f64 = inliers.iter().map(|hit| hit.2 * (hit.0 - mean_sample).powi(2)).sum();
+        // Inliers all at one clip time leave the slope to the search
+        if variance > 0.0 {
+            slope = (covariance / variance).clamp(search_slope - step, search_slope + step);
+        }
+        offset = mean_song - slope * mean_sample;
+    }
+
+    let on_line: Vec<(f64, f64)> = hits.iter()
+        .map(|hit| (hit.1 - slope * hit.0 - offset, hit.2))
+        .filter(|(residual, _)| residual.abs() <= tolerance)
+        .collect();
+    let total_weight: f64 = on_line.iter().map(|(_, weight)| weight).sum();
+    if total_weight <= 0.0 {
+        return None;
+    }
+    let votes = on_line.iter().map(|&(_, weight)| (weight * VOTES_PER_HASH) as usize).sum();
+    let shift = on_line.iter().map(|(residual, weight)| residual * weight).sum::<f64>() / total_weight;
+    let variance = on_line.iter().map(|(residual, weight)| weight * (residual - shift).powi(2)).sum::<f64>() / total_weight;
+
+    Some((OffsetPeak { votes, offset: offset + shift, spread: variance.sqrt(), background: peak.background }, slope))
+}
+
+// Scores every song in `matches` against the clip and returns those passing the thresholds
+// in `config`, highest confidence first. `clip_duration` is the clip's length in seconds.
+pub fn rank_matches(
+    matches: &[(u64, u64, f64)],
+    keys: &[u64],
+    values: &[f64],
+    clip_duration: f64,
+    hash_weights: Option<&HashWeights>,
+    config: &MatchConfig
+) -> Vec<MatchCandidate> {
 
     if matches.is_empty() {
-        println!("No songs found to match against.");
-        return Ok((0.0, 0));  // Return a default value instead of an error
+        log::info!("No songs found to match against.");
+        return Vec::new();
     }
 
     let sample = Song::new_sample(0, keys, values);
@@ -79,7 +411,7 @@ pub fn match_song(
     let mut songs_counter: usize = 0;
 
     // Group matches by song
-    for row in &matches {
+    for row in matches {
         if !songs_hashes.is_empty() && songs_hashes[songs_counter-1].id == row.1 {
             songs_hashes[songs_counter-1].add(row.0, row.2);
         } else {
@@ -92,26 +424,28 @@ pub fn match_song(
     
     // Early return if no songs were processed
     if songs_hashes.is_empty() {
-        println!("No valid songs to analyze.");
-        return Ok((0.0, 0));
+        log::info!("No valid songs to analyze.");
+        return Vec::new();
     }
     
-    // Maps to track both song-offset pairs and their match counts
-    let mut offset_counts: HashMap<(u64, i32), usize> = HashMap::new();
-    
     // Maps to track hash frequency across songs (for identifying common/ambiguous hashes)
     let mut hash_frequency: HashMap<u64, usize> = HashMap::new();
     
-    // First pass: count hash frequency across all songs
-    for song in &songs_hashes {
+    // First pass: count hash frequency across all songs, unless catalogue-wide weights are given
+    for song in songs_hashes.iter().filter(|_| hash_weights.is_none()) {
         for &hash in &song.hashes {
             *hash_frequency.entry(hash).or_insert(0) += 1;
         }
     }
     
-    // Second pass: perform matching with consideration for hash uniqueness
+    // Second pass: collect each song's (clip time, song time, weight) hits, with consideration
+    // for hash uniqueness, and keep its best alignment over the tempo factors tried
+    let mut peaks: Vec<(u64, OffsetPeak, f64)> = Vec::new();
+    let mut songs_hits: Vec<Vec<(f64, f64, f64)>> = Vec::new();
+
     for song in &songs_hashes {
         let song_hash_map = song.create_hashmap();
+        let mut hits: Vec<(f64, f64, f64)> = Vec::new();
         
         // For each hash in the sample
         for (i, &sample_hash) in sample.hashes.iter().enumerate() {
@@ -119,78 +453,129 @@ pub fn match_song(
             
             // If this hash exists in the song
             if let Some(song_times) = song_hash_map.get(&sample_hash) {
-                // Get the frequency of this hash across all songs
-                let frequency = hash_frequency.get(&sample_hash).unwrap_or(&1);
-                
-                // Weight inversely by frequency - more common hashes get lower weight
-                let weight = 1.0 / (*frequency as f64).max(1.0);
+                // Weight inversely by frequency - more common hashes get lower weight.
+                // Prefer catalogue-wide counts; fall back to the songs matched by this query.
+                let weight = match hash_weights {
+                    Some(weights) => weights.weight(sample_hash),
+                    None => {
+                        let frequency = hash_frequency.get(&sample_hash).unwrap_or(&1);
+                        1.0 / (*frequency as f64).max(1.0)
+                    }
+                };
                 
                 // Handle collision: Consider all possible time positions for this hash
                 for &song_time in song_times {
-                    // Calculate the time offset (keeping the sign)
-                    let raw_offset = (song_time - sample_time) * config.precision_factor;
-                    
-                    // Bin the offsets to handle slight timing variations
-                    let offset_bin = (raw_offset as i32) / config.offset_bin_size * config.offset_bin_size;
-                    
-                    let key = (song.id, offset_bin);
-                    *offset_counts.entry(key).or_insert(0) += (weight * 100.0) as usize;
+                    hits.push((sample_time, song_time, weight));
                 }
             }
         }
+
+        // The offset keeps its sign: negative when the clip starts before the song
+        let tempo_factor = best_tempo_factor(&hits, config);
+        if let Some(mut peak) = peak_at(&hits, tempo_factor, config) {
+            let tempo_factor = refine_tempo_factor(&hits, &mut peak, tempo_factor, config);
+            peaks.push((song.id, peak, tempo_factor));
+            songs_hits.push(hits);
+        }
     }
 
-    // Reorganize offset counts by song ID for analysis
-    let mut song_match_counts: HashMap<u64, HashMap<i32, usize>> = HashMap::new();
-    
-    for ((song_id, offset), count) in offset_counts {
-        song_match_counts
-            .entry(song_id)
-            .or_insert_with(HashMap::new)
-            .insert(offset, count);
+    // Third pass: rescore the strongest songs by the hits on their best line. Their runner-up
+    // is among them, so the margins compare like with like.
+    let mut strongest: Vec<usize> = (0..peaks.len()).collect();
+    strongest.sort_by_key(|&i| std::cmp::Reverse(peaks[i].1.votes));
+    for &i in strongest.iter().take(config.line_fit_candidates) {
+        let (_, peak, tempo_factor) = &peaks[i];
+        if let Some((line, slope)) = fit_line(&songs_hits[i], peak, *tempo_factor, clip_duration, config) {
+            peaks[i].1 = line;
+            peaks[i].2 = slope;
+        }
     }
-    
-    // Find the best match for each song
-    let mut best_matches: Vec<(u64, i32, usize, f64)> = Vec::new();
-    
-    for (song_id, offsets) in song_match_counts {
-        if let Some((&best_offset, &count)) = offsets.iter().max_by_key(|&(_, count)| count) {
-            // Skip if below minimum match threshold
-            if count < config.min_match_threshold {
-                continue;
-            }
-            
-            // Find the matching song to calculate confidence
-            if let Some(matched_song) = songs_hashes.iter().find(|s| s.id == song_id) {
-                let confidence = (count as f64 / matched_song.hashes.len() as f64) * 100.0;
-                
-                // Skip if below minimum confidence threshold
-                if confidence < config.min_confidence {
-                    continue;
-                }
-                
-                best_matches.push((song_id, best_offset, count, confidence));
-            }
+
+    // The two strongest peaks: each song's runner-up is the strongest of the other songs, or
+    // its own strongest chance alignment when no other song matched the clip
+    let (mut first, mut second) = (0usize, 0usize);
+    for (_, peak, _) in &peaks {
+        if peak.votes > first {
+            second = first;
+            first = peak.votes;
+        } else if peak.votes > second {
+            second = peak.votes;
         }
     }
+
+    let peaks_count = peaks.len();
+    let clip_hashes = keys.len().max(1) as f64;
+    let mut best_matches: Vec<MatchCandidate> = Vec::new();
+    for (song_id, peak, tempo_factor) in peaks {
+        // Skip if below minimum match threshold
+        if peak.votes < config.min_match_threshold {
+            continue;
+        }
+
+        let runner_up = match peaks_count {
+            1 => peak.background,
+            _ if peak.votes == first => second,
+            _ => first,
+        };
+        let hash_fraction = peak.votes as f64 / VOTES_PER_HASH / clip_hashes;
+        let margin = peak.votes as f64 / (runner_up as f64).max(VOTES_PER_HASH);
+        let confidence = config.calibration.probability(hash_fraction, margin) * 100.0;
+        // Skip if below minimum confidence threshold
+        if confidence < config.min_confidence {
+            continue;
+        }
+
+        let start = to_frame_precision(peak.offset);
+        best_matches.push(MatchCandidate {
+            song_id,
+            offset: peak.offset,
+            tempo_factor,
+            offset_spread: peak.spread,
+            start: start.max(0.0),
+            end: (start + to_frame_precision(clip_duration * tempo_factor)).max(0.0),
+            weighted_matches: peak.votes,
+            hash_fraction,
+            margin,
+            confidence,
+        });
+    }
+    
+    // Sort matches by confidence (highest first), then by weighted matches
+    best_matches.sort_by(|a, b| {
+        b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.weighted_matches.cmp(&a.weighted_matches))
+    });
+
+    log::info!("Number of songs analyzed: {}", songs_counter);
+    log::info!("Total number of fingerprint matches: {}", matches.len());
+
+    best_matches
+}
+
+// Logs the top candidates and returns the best one, or None when nothing passes the thresholds
+pub fn match_song(
+    matches: Vec<(u64, u64, f64)>, 
+    keys: &[u64], 
+    values: &[f64],
+    clip_duration: f64,
+    hash_weights: Option<&HashWeights>,
+    config: Option<MatchConfig>
+) -> Result<Option<MatchCandidate>, Box<dyn std::error::Error>> {
     
-    // Sort matches by confidence (highest first)
-    best_matches.sort_by(|a, b| b.3.partial_cmp(&a.3).unwrap_or(std::cmp::Ordering::Equal));
+    let config = config.unwrap_or_default();
+    let best_matches = rank_matches(&matches, keys, values, clip_duration, hash_weights, &config);
     
     // Handle empty results
     if best_matches.is_empty() {
-        println!("No matches met the minimum criteria (threshold: {} matches, {:.1}% confidence)",
+        log::info!("No matches met the minimum criteria (threshold: {} matches, {:.1}% confidence)",
                 config.min_match_threshold, config.min_confidence);
-        return Ok((0.0, 0));  // Return default value for no matches
+        return Ok(None);
     }
     
     // Display top matches
-    let mut top_confidence: f64 = 0.0;
-    let mut top_song_id: u64 = 0;
-    
-    println!("Top matches:");
-    for (i, (song_id, offset, count, confidence)) in best_matches.iter().take(config.max_results).enumerate() {
-        let offset_seconds = *offset as f64 / config.precision_factor;
+    log::info!("Top matches:");
+    for (i, candidate) in best_matches.iter().take(config.max_results).enumerate() {
+        let offset_seconds = candidate.offset;
         
         // Whether the sample is earlier or later than the matched song
         let position = if offset_seconds > 0.0 {
@@ -201,17 +586,37 @@ pub fn match_song(
             "exact match"
         };
         
-        println!("{}. Song ID {}: {} weighted matches, {:.2}% confidence at offset {:.3} seconds ({})", 
-                 i+1, song_id, count, confidence, offset_seconds.abs(), position);
-        
-        if *confidence > top_confidence {
-            top_confidence = *confidence;
-            top_song_id = *song_id;
-        }
+        log::info!("{}. Song ID {}: {} weighted matches ({:.1}% of the clip, {:.2}x the runner-up), {:.2}% confidence at offset {:.3} (+-{:.3}) seconds ({}), tempo x{:.3}, clip spans {:.3}-{:.3} s of the song", 
+                 i+1, candidate.song_id, candidate.weighted_matches, candidate.hash_fraction * 100.0, candidate.margin, candidate.confidence, offset_seconds.abs(), candidate.offset_spread, position, candidate.tempo_factor, candidate.start, candidate.end);
     }
     
-    println!("Number of songs analyzed: {}", songs_counter);
-    println!("Total number of fingerprint matches: {}", matches.len());
-    
-    Ok((top_confidence, top_song_id))
-}
\ No newline at end of file
+    // Candidates are sorted by confidence
+    Ok(best_matches.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_weights_fall_as_more_songs_carry_the_hash() {
+        let weights = HashWeights::new(100, HashMap::from([(1, 1), (2, 10), (3, 100)]));
+
+        assert!((weights.weight(1) - 1.0).abs() < 1e-12);
+        assert!(weights.weight(2) < weights.weight(1));
+        assert!(weights.weight(3) < weights.weight(2));
+        assert!((weights.weight(3) - 2f64.ln() / 101f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hash_weights_treat_unknown_and_out_of_range_counts_as_bounded() {
+        let weights = HashWeights::new(10, HashMap::from([(1, 0), (2, 50)]));
+
+        assert!((weights.weight(1) - 1.0).abs() < 1e-12, "a count of 0 weighs like a single song");
+        assert!((weights.weight(7) - 1.0).abs() < 1e-12, "a hash with no count weighs like a single song");
+        assert!((weights.weight(2) - 2f64.ln() / 11f64.ln()).abs() < 1e-12, "counts cap at the catalogue size");
+
+        let empty = HashWeights::new(0, HashMap::new());
+        assert!((empty.weight(1) - 1.0).abs() < 1e-12);
+    }
+}
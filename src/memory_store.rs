@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::fingerprint::SchemeParameters;
+use crate::fingerprint_store::*;
+
+#[derive(Default)]
+struct Catalogue {
+    next_song_id: u64,
+    songs: BTreeMap<u64, SongMetadata>,
+    // hash_key -> [(song_id, anchor_time)]
+    fingerprints: HashMap<u64, Vec<(u64, f64)>>,
+    // song_id -> hashes the stop-list kept out of `fingerprints`
+    stopped: HashMap<u64, HashSet<u64>>,
+    // hash_key -> number of distinct songs carrying it, stored or stopped
+    song_counts: HashMap<u64, u64>,
+    scheme: Option<SchemeParameters>,
+}
+
+// Fingerprint store kept entirely in process memory. Nothing is persisted, which makes it
+// suitable for tests and for small embedded catalogues that are rebuilt on start-up.
+#[derive(Default)]
+pub struct MemoryStore {
+    catalogue: RwLock<Catalogue>,
+}
+
+impl Catalogue {
+    fn carries(&self, song_id: u64, key: u64) -> bool {
+        self.fingerprints.get(&key).is_some_and(|postings| postings.iter().any(|&(id, _)| id == song_id))
+            || self.stopped.get(&song_id).is_some_and(|keys| keys.contains(&key))
+    }
+
+    // Adds the song to the count of each of `keys` it did not carry yet
+    fn count_song_carrying(&mut self, song_id: u64, keys: &[u64]) {
+        let new_keys: HashSet<u64> = keys.iter().copied().filter(|&key| !self.carries(song_id, key)).collect();
+        for key in new_keys {
+            *self.song_counts.entry(key).or_default() += 1;
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, Catalogue>, Box<dyn std::error::Error>> {
+        self.catalogue.read().map_err(|_| "Memory store lock poisoned".into())
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, Catalogue>, Box<dyn std::error::Error>> {
+        self.catalogue.write().map_err(|_| "Memory store lock poisoned".into())
+    }
+}
+
+impl FingerprintStore for MemoryStore {
+    fn register_song(&self, metadata: &SongMetadata) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut catalogue = self.write()?;
+
+        if catalogue.songs.values().any(|song| song.same_recording(metadata)) {
+            return Err(metadata.duplicate_error());
+        }
+
+        // Ids start at 1 like an AUTO_INCREMENT column; 0 means "no match" elsewhere
+        catalogue.next_song_id += 1;
+        let song_id = catalogue.next_song_id;
+        catalogue.songs.insert(song_id, metadata.clone());
+
+        Ok(song_id)
+    }
+
+    fn insert_hashes(&self, song_id: u64, keys: &[u64], values: &[f64]) -> Result<usize, Box<dyn std::error::Error>> {
+        if keys.len() != values.len() {
+            return Err("Keys and values vectors must have the same length".into());
+        }
+
+        let mut catalogue = self.write()?;
+        if !catalogue.songs.contains_key(&song_id) {
+            return Err("Song not found".into());
+        }
+
+        catalogue.count_song_carrying(song_id, keys);
+        for (&key, &value) in keys.iter().zip(values) {
+            catalogue.fingerprints.entry(key).or_default().push((song_id, value));
+        }
+
+        Ok(keys.len())
+    }
+
+    fn insert_stopped_hashes(&self, song_id: u64, keys: &[u64]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut catalogue = self.write()?;
+        if !catalogue.songs.contains_key(&song_id) {
+            return Err("Song not found".into());
+        }
+
+        catalogue.count_song_carrying(song_id, keys);
+        catalogue.stopped.entry(song_id).or_default().extend(keys);
+        Ok(())
+    }
+
+    fn lookup_hashes(&self, keys: &[u64]) -> Result<Vec<FingerprintRow>, Box<dyn std::error::Error>> {
+        let mut unique_keys = keys.to_vec();
+        unique_keys.sort_unstable();
+        unique_keys.dedup();
+
+        let catalogue = self.read()?;
+        let mut result: Vec<FingerprintRow> = Vec::new();
+        for key in unique_keys {
+            if let Some(postings) = catalogue.fingerprints.get(&key) {
+                result.extend(postings.iter().map(|&(song_id, anchor_time)| (key, song_id, anchor_time)));
+            }
+        }
+
+        result.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        Ok(result)
+    }
+
+    fn song_info(&self, song_id: u64) -> Result<SongInfo, Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+
+        match catalogue.songs.get(&song_id) {
+            Some(metadata) => Ok(SongInfo { id: song_id, metadata: metadata.clone() }),
+            None => Err("Song not found".into()),
+        }
+    }
+
+    fn delete_song(&self, song_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let mut catalogue = self.write()?;
+
+        if catalogue.songs.remove(&song_id).is_none() {
+            return Err("Song not found".into());
+        }
+        let catalogue = &mut *catalogue;
+        let mut carried: HashSet<u64> = catalogue.stopped.remove(&song_id).unwrap_or_default();
+        catalogue.fingerprints.retain(|&key, postings| {
+            let before = postings.len();
+            postings.retain(|&(id, _)| id != song_id);
+            if postings.len() != before {
+                carried.insert(key);
+            }
+            !postings.is_empty()
+        });
+        for key in carried {
+            if let Some(count) = catalogue.song_counts.get_mut(&key) {
+                *count -= 1;
+            }
+        }
+        catalogue.song_counts.retain(|_, count| *count > 0);
+
+        Ok(())
+    }
+
+    fn list_songs(&self) -> Result<Vec<SongInfo>, Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+        Ok(catalogue.songs.iter().map(|(&id, metadata)| SongInfo { id, metadata: metadata.clone() }).collect())
+    }
+
+    fn song_hashes(&self, song_id: u64) -> Result<(Vec<u64>, Vec<f64>), Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+        if !catalogue.songs.contains_key(&song_id) {
+            return Err("Song not found".into());
+        }
+
+        let mut rows: Vec<(f64, u64)> = Vec::new();
+        for (&key, postings) in &catalogue.fingerprints {
+            rows.extend(postings.iter().filter(|&&(id, _)| id == song_id).map(|&(_, anchor_time)| (anchor_time, key)));
+        }
+
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        Ok(rows.into_iter().map(|(time, hash)| (hash, time)).unzip())
+    }
+
+    fn song_count(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self.read()?.songs.len() as u64)
+    }
+
+    fn hash_song_counts(&self, keys: &[u64]) -> Result<HashMap<u64, u64>, Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+        Ok(keys.iter().filter_map(|key| catalogue.song_counts.get(key).map(|&count| (*key, count))).collect())
+    }
+
+    fn fingerprint_counts(&self) -> Result<Vec<(u64, u64)>, Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+
+        let mut counts: BTreeMap<u64, u64> = catalogue.songs.keys().map(|&id| (id, 0)).collect();
+        for postings in catalogue.fingerprints.values() {
+            for &(song_id, _) in postings {
+                *counts.entry(song_id).or_default() += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    fn most_frequent_hashes(&self, limit: usize) -> Result<Vec<HashFrequency>, Box<dyn std::error::Error>> {
+        let catalogue = self.read()?;
+
+        let mut frequencies: Vec<HashFrequency> = catalogue.fingerprints.iter().map(|(&hash, postings)| {
+            let mut song_ids: Vec<u64> = postings.iter().map(|&(song_id, _)| song_id).collect();
+            song_ids.sort_unstable();
+            song_ids.dedup();
+            HashFrequency { hash, occurrences: postings.len() as u64, songs: song_ids.len() as u64 }
+        }).collect();
+
+        frequencies.sort_by(|a, b| b.songs.cmp(&a.songs).then(a.hash.cmp(&b.hash)));
+        frequencies.truncate(limit);
+        Ok(frequencies)
+    }
+
+    fn scheme_parameters(&self) -> Result<Option<SchemeParameters>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.scheme.clone())
+    }
+
+    fn record_scheme_parameters(&self, parameters: &SchemeParameters) -> Result<(), Box<dyn std::error::Error>> {
+        self.write()?.scheme.get_or_insert_with(|| parameters.clone());
+        Ok(())
+    }
+}
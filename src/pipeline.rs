@@ -0,0 +1,9 @@
+// The individual stages behind `Fingerprint` and `ShazamEngine`, for benchmarks and
+// experiments that need to run or time one stage on its own:
+// WAV decoding and resampling -> STFT -> peak picking -> hashing -> scoring
+pub use crate::audio_processing::{process_audio, process_wav_file, resample_to_output};
+pub use crate::create_hash::create_pairs;
+pub use crate::create_triplets::create_triplets;
+pub use crate::make_spectr::window_audio;
+pub use crate::match_song::{HashWeights, MatchConfig, match_song, rank_matches};
+pub use crate::process_spectr::find_spectral_peaks;
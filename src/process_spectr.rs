@@ -1,17 +1,17 @@
 #![allow(dead_code)]
 use ndarray::Array2;
-use std::fs::File;
-use std::io::{Write, BufWriter};
+use std::io::Write;
+use crate::debug_log::create_log;
 
-pub fn convert_to_ndarray(spectrogram: &Vec<Vec<f64>>) -> Array2<f64> {
+pub fn convert_to_ndarray(spectrogram: &[Vec<f64>]) -> Array2<f64> {
     let rows = spectrogram.len();
-    let cols = spectrogram[0].len();
+    let cols = spectrogram.first().map_or(0, Vec::len);
     let flat_data: Vec<f64> = spectrogram.iter().flatten().copied().collect();
     Array2::from_shape_vec((rows, cols), flat_data).expect("Failed to create ndarray")
 }
 
 
-pub fn find_spectral_peaks(spectrogram: &Vec<Vec<f64>>) -> Result<Vec<Vec<usize>>, Box<dyn std::error::Error>> {
+pub fn find_spectral_peaks(spectrogram: &[Vec<f64>]) -> Result<Vec<Vec<usize>>, Box<dyn std::error::Error>> {
 
     let spectrogram = &convert_to_ndarray(spectrogram);
     let mut peaks: Vec<Vec<usize>> = vec![Vec::new(); spectrogram.nrows()];
@@ -53,18 +53,18 @@ pub fn find_spectral_peaks(spectrogram: &Vec<Vec<f64>>) -> Result<Vec<Vec<usize>
     //         println!("Peak number {:?}: {:?}", j, spectrogram[[i, proccessed_peaks[i][j]]]);
     //     }
     // }
-    let file = File::create("../log/filtered_peaks_testing.txt")?;
-    let mut writer = BufWriter::new(file);
-    for i in 0..proccessed_peaks.len() {
-        writeln!(writer, "The peaks at row {:?}: ", i)?;
-        for j in 0..proccessed_peaks[i].len() {
-            writeln!(writer, "Peak number {:?}: {:?}", j, spectrogram[[i, proccessed_peaks[i][j]]])?;
+    if let Some(mut writer) = create_log("filtered_peaks_testing.txt")? {
+        for i in 0..proccessed_peaks.len() {
+            writeln!(writer, "The peaks at row {:?}: ", i)?;
+            for j in 0..proccessed_peaks[i].len() {
+                writeln!(writer, "Peak number {:?}: {:?}", j, spectrogram[[i, proccessed_peaks[i][j]]])?;
+            }
         }
     }
     Ok(proccessed_peaks.clone())
 }
 
-pub fn filter_peaks_by_threshold(peaks: &mut Vec<Vec<usize>>, spectrogram: &Array2<f64>) -> Vec<Vec<usize>> {
+pub fn filter_peaks_by_threshold(peaks: &mut [Vec<usize>], spectrogram: &Array2<f64>) -> Vec<Vec<usize>> {
     let mut filtered_peaks = vec![Vec::new(); peaks.len()];
     
     // For each time frame
@@ -86,7 +86,7 @@ pub fn filter_peaks_by_threshold(peaks: &mut Vec<Vec<usize>>, spectrogram: &Arra
             let threshold: f64 = band_magnitudes.iter().sum::<f64>() / 6.0;
             
             // Keep only peaks that exceed the threshold
-            for (_j, &freq_idx) in peaks[i].iter().enumerate() {
+            for &freq_idx in &peaks[i] {
                 if freq_idx < spectrogram.ncols() && spectrogram[[i, freq_idx]] > threshold {
                     filtered_peaks[i].push(freq_idx);
                 }
@@ -94,5 +94,5 @@ pub fn filter_peaks_by_threshold(peaks: &mut Vec<Vec<usize>>, spectrogram: &Arra
         }
     }
     
-    return filtered_peaks;
+    filtered_peaks
 }
\ No newline at end of file
@@ -0,0 +1,387 @@
+use hound::WavReader;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use crate::engine::{MatchCandidate, ShazamEngine};
+use crate::fingerprint::Fingerprint;
+
+// Audio read a stretch at a time, so a long recording never has to be in memory whole
+pub trait AudioSource {
+    // Sample rate of the samples `read` returns
+    fn sample_rate(&self) -> f64;
+    // Length in seconds
+    fn duration(&self) -> f64;
+    // Mono samples from `start` to `end` seconds, cut short at the end of the audio
+    fn read(&mut self, start: f64, end: f64) -> Result<Vec<f64>, Box<dyn std::error::Error>>;
+}
+
+// First and last sample frame of `start` to `end` seconds, within `frames`
+fn frame_range(start: f64, end: f64, sample_rate: f64, frames: usize) -> (usize, usize) {
+    let first = ((start.max(0.0) * sample_rate).round() as usize).min(frames);
+    let last = ((end.max(0.0) * sample_rate).round() as usize).clamp(first, frames);
+    (first, last)
+}
+
+// Mono samples already in memory
+pub struct SampleSource<'a> {
+    samples: &'a [f64],
+    sample_rate: f64,
+}
+
+impl<'a> SampleSource<'a> {
+    pub fn new(samples: &'a [f64], sample_rate: f64) -> Self {
+        Self { samples, sample_rate }
+    }
+}
+
+impl AudioSource for SampleSource<'_> {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.sample_rate
+    }
+
+    fn read(&mut self, start: f64, end: f64) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+        let (first, last) = frame_range(start, end, self.sample_rate, self.samples.len());
+        Ok(self.samples[first..last].to_vec())
+    }
+}
+
+// A 16-bit WAV file at any path, mixed down to mono as for fingerprinting but decoded only
+// where it is read
+pub struct WavSource {
+    reader: WavReader<BufReader<File>>,
+}
+
+impl WavSource {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = WavReader::open(path)?;
+        if reader.duration() == 0 {
+            return Err("Input file contains no samples".into());
+        }
+        Ok(Self { reader })
+    }
+}
+
+impl AudioSource for WavSource {
+    fn sample_rate(&self) -> f64 {
+        self.reader.spec().sample_rate as f64
+    }
+
+    fn duration(&self) -> f64 {
+        self.reader.duration() as f64 / self.sample_rate()
+    }
+
+    fn read(&mut self, start: f64, end: f64) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+        let (first, last) = frame_range(start, end, self.sample_rate(), self.reader.duration() as usize);
+        let channels = self.reader.spec().channels as usize;
+        self.reader.seek(first as u32)?;
+        let samples: Vec<i16> = self.reader.samples::<i16>().take((last - first) * channels).collect::<Result<_, _>>()?;
+        Ok(samples.chunks(channels)
+            .map(|frame| frame.iter().map(|&sample| sample as f64 / 32768.0).sum::<f64>() / channels as f64)
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentationOptions {
+    // Length of each matched window and the step between window starts, in seconds
+    pub window: f64,
+    pub hop: f64,
+    // Windows whose best candidate is below this confidence (%) count as unknown
+    pub min_confidence: f64,
+    // Largest disagreement, in seconds of song time, between the alignments of consecutive
+    // windows of one segment. A song that skips or repeats a passage starts a new segment.
+    pub offset_tolerance: f64,
+    // Unknown stretches shorter than this many seconds between songs, or at either end of the
+    // recording, are given to the songs next to them
+    pub min_gap: f64,
+}
+
+impl Default for SegmentationOptions {
+    fn default() -> Self {
+        Self { window: 10.0, hop: 5.0, min_confidence: 50.0, offset_tolerance: 0.5, min_gap: 1.0 }
+    }
+}
+
+// A stretch of the recording playing one song, or that no window recognized
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Segment {
+    // Position in the recording, in seconds
+    pub start: f64,
+    pub end: f64,
+    // The song playing, None for an unknown stretch
+    pub song_id: Option<u64>,
+    // Song time at the segment's start in seconds
+    pub offset: Option<f64>,
+    // Song seconds per recording second
+    pub tempo_factor: Option<f64>,
+    // Highest confidence (%) among the segment's windows
+    pub confidence: Option<f64>,
+    // Windows recognized as this song; 0 for unknown stretches
+    pub windows: usize,
+}
+
+impl Segment {
+    fn unknown(start: f64, end: f64) -> Self {
+        Self { start, end, song_id: None, offset: None, tempo_factor: None, confidence: None, windows: 0 }
+    }
+}
+
+// One window, fingerprinted on its own, and its best candidate if it passed the confidence
+// threshold
+struct Window {
+    start: f64,
+    end: f64,
+    clip: Fingerprint,
+    candidate: Option<MatchCandidate>,
+}
+
+impl Window {
+    // The window's position and candidate, without its hashes
+    fn without_hashes(&self) -> Self {
+        Self { start: self.start, end: self.end, clip: self.clip.excerpt(0.0, 0.0), candidate: self.candidate.clone() }
+    }
+
+    // Song time at recording time `time`, following the window's alignment
+    fn song_time(&self, time: f64) -> Option<f64> {
+        self.candidate.as_ref().map(|candidate| candidate.offset + candidate.tempo_factor * (time - self.start))
+    }
+}
+
+// Consecutive windows agreeing on a song and its alignment. `first` is the first window
+// without its hashes, kept for its start and candidate.
+struct Run {
+    first: Window,
+    start: f64,
+    end: f64,
+    windows: usize,
+    confidence: f64,
+}
+
+// Stored hashes within about a frame of a window's alignment count as the song playing
+const ALIGNMENT_TOLERANCE: f64 = 0.07;
+
+// Window starts every `hop` seconds, the last covering the end of the recording
+fn window_starts(duration: f64, options: &SegmentationOptions) -> impl Iterator<Item = f64> + '_ {
+    (0..)
+        .map(|n| n as f64 * options.hop)
+        .take_while(move |&start| start == 0.0 || start + options.window - options.hop < duration)
+}
+
+fn is_consistent(previous: &Window, window: &Window, options: &SegmentationOptions) -> bool {
+    let (Some(previous_candidate), Some(candidate)) = (&previous.candidate, &window.candidate) else {
+        return false;
+    };
+    let expected = previous.song_time(window.start).unwrap_or_default();
+    previous_candidate.song_id == candidate.song_id && (candidate.offset - expected).abs() <= options.offset_tolerance
+}
+
+// Recording time of every hash in the window, and whether its song holds it at the window's
+// alignment, in time order
+fn aligned_hashes(engine: &ShazamEngine, window: &Window) -> Result<Vec<(f64, bool)>, Box<dyn std::error::Error>> {
+    let Some(candidate) = &window.candidate else {
+        return Ok(Vec::new());
+    };
+    let mut song_times: HashMap<u64, Vec<f64>> = HashMap::new();
+    for (hash, song_id, song_time) in engine.store().lookup_hashes(&window.clip.hashes)? {
+        if song_id == candidate.song_id {
+            song_times.entry(hash).or_default().push(song_time);
+        }
+    }
+
+    let mut hashes: Vec<(f64, bool)> = window.clip.hashes.iter().zip(&window.clip.anchor_times)
+        .map(|(hash, &time)| {
+            let expected = window.song_time(window.start + time).unwrap_or_default();
+            let aligned = song_times.get(hash).is_some_and(|times| times.iter().any(|song_time| (song_time - expected).abs() <= ALIGNMENT_TOLERANCE));
+            (window.start + time, aligned)
+        })
+        .collect();
+    hashes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(hashes)
+}
+
+// The split of `hashes` into two parts whose shares of aligned hashes differ the most, weighed
+// by the size of the parts (a least-squares change point): the index where the second part
+// starts, and the share in each part
+fn change_point(hashes: &[(f64, bool)]) -> Option<(usize, f64, f64)> {
+    let total = hashes.iter().filter(|hash| hash.1).count() as f64;
+    let count = hashes.len() as f64;
+    let mut before = 0.0;
+    let mut best: Option<(f64, usize, f64, f64)> = None;
+    for (split, hash) in hashes.iter().enumerate().skip(1) {
+        before += hashes[split - 1].1 as usize as f64;
+        // Hashes of one frame stay together
+        if hash.0 == hashes[split - 1].0 {
+            continue;
+        }
+        let (share_before, share_after) = (before / split as f64, (total - before) / (count - split as f64));
+        let score = split as f64 * (count - split as f64) * (share_before - share_after).powi(2);
+        if best.is_none_or(|(best_score, _, _, _)| score > best_score) {
+            best = Some((score, split, share_before, share_after));
+        }
+    }
+    best.map(|(_, split, share_before, share_after)| (split, share_before, share_after))
+}
+
+// Narrows runs from their windows to where their song plays: a window straddling two songs, or
+// a song and unknown audio, is recognized as one of them. A run starts after a change point in
+// its first window where fewer than half as many of the hashes before it are on the song's
+// alignment as after it, and ends likewise in its last window.
+fn song_start(engine: &ShazamEngine, first: &Window) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    let hashes = aligned_hashes(engine, first)?;
+    Ok(change_point(&hashes)
+        .filter(|&(_, before, after)| before < after / 2.0)
+        .and_then(|(split, _, _)| hashes[split..].iter().find(|hash| hash.1))
+        .map(|&(time, _)| time))
+}
+
+fn song_end(engine: &ShazamEngine, last: &Window) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    let hashes = aligned_hashes(engine, last)?;
+    Ok(change_point(&hashes)
+        .filter(|&(_, before, after)| after < before / 2.0)
+        .and_then(|(split, _, _)| hashes[..split].iter().rev().find(|hash| hash.1))
+        .map(|&(time, _)| time))
+}
+
+// Groups windows into runs as they arrive in recording order, holding on to the hashes of the
+// open run's last window only
+struct RunBuilder<'a> {
+    engine: &'a ShazamEngine,
+    options: &'a SegmentationOptions,
+    // The open run and the window before the next one, if that window extended it
+    open: Option<(Run, Window)>,
+    runs: Vec<Run>,
+}
+
+impl<'a> RunBuilder<'a> {
+    fn new(engine: &'a ShazamEngine, options: &'a SegmentationOptions) -> Self {
+        Self { engine, options, open: None, runs: Vec::new() }
+    }
+
+    fn push(&mut self, window: Window) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(confidence) = window.candidate.as_ref().map(|candidate| candidate.confidence) else {
+            return self.close();
+        };
+        if let Some((run, last)) = self.open.as_mut().filter(|(_, last)| is_consistent(last, &window, self.options)) {
+            run.end = window.end;
+            run.windows += 1;
+            run.confidence = run.confidence.max(confidence);
+            *last = window;
+            return Ok(());
+        }
+
+        self.close()?;
+        let start = song_start(self.engine, &window)?.unwrap_or(window.start);
+        let run = Run { first: window.without_hashes(), start, end: window.end, windows: 1, confidence };
+        self.open = Some((run, window));
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some((mut run, last)) = self.open.take() {
+            if let Some(end) = song_end(self.engine, &last)? {
+                run.end = end.max(run.start);
+            }
+            self.runs.push(run);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<Run>, Box<dyn std::error::Error>> {
+        self.close()?;
+        Ok(self.runs)
+    }
+}
+
+// Splits overlaps between neighbouring runs, and gaps shorter than `options.min_gap`, halfway.
+// Gaps that short at either end of the recording go to the run next to them.
+fn settle_boundaries(runs: &mut [Run], duration: f64, options: &SegmentationOptions) {
+    for i in 1..runs.len() {
+        let (before, after) = runs.split_at_mut(i);
+        let (previous, run) = (&mut before[i - 1], &mut after[0]);
+        if run.start - previous.end < options.min_gap {
+            let boundary = ((run.start + previous.end) / 2.0).clamp(previous.start, run.end);
+            previous.end = boundary;
+            run.start = boundary;
+        }
+    }
+    if let Some(run) = runs.first_mut().filter(|run| run.start < options.min_gap) {
+        run.start = 0.0;
+    }
+    if let Some(run) = runs.last_mut().filter(|run| duration - run.end < options.min_gap) {
+        run.end = duration;
+    }
+}
+
+// Fingerprints one window of the recording on its own and recognizes it
+fn recognize_window(engine: &ShazamEngine, start: f64, samples: &[f64], sample_rate: f64, options: &SegmentationOptions) -> Result<Window, Box<dyn std::error::Error>> {
+    let clip = Fingerprint::from_samples_using(samples, sample_rate, engine.scheme())?;
+    let candidate = engine.recognize_candidates(&clip)?
+        .into_iter()
+        .next()
+        .filter(|candidate| candidate.confidence >= options.min_confidence);
+    Ok(Window { start, end: start + samples.len() as f64 / sample_rate, clip, candidate })
+}
+
+// Splits a long recording, such as a radio broadcast or a DJ mix, into the songs it plays.
+// Windows of `options.window` seconds every `options.hop` seconds are read from `source`,
+// fingerprinted and recognized on their own, as many at a time as the current rayon thread
+// pool has threads, so memory stays bounded by the window length however long the recording.
+// Consecutive windows that agree on a song and its alignment merge into one segment, which
+// ends at the first and last of its hashes on that alignment. The timeline covers the whole
+// recording in order, with unknown segments wherever no song was recognized.
+pub fn segment_recording(engine: &ShazamEngine, source: &mut dyn AudioSource, options: &SegmentationOptions) -> Result<Vec<Segment>, Box<dyn std::error::Error>> {
+    if options.window <= 0.0 || options.hop <= 0.0 || options.hop > options.window {
+        return Err(format!("Windows of {} s every {} s do not cover the recording", options.window, options.hop).into());
+    }
+
+    let (duration, sample_rate) = (source.duration(), source.sample_rate());
+    let mut starts = window_starts(duration, options).peekable();
+    let mut builder = RunBuilder::new(engine, options);
+    while starts.peek().is_some() {
+        let batch: Vec<(f64, Vec<f64>)> = starts.by_ref()
+            .take(rayon::current_num_threads())
+            .map(|start| Ok((start, source.read(start, start + options.window)?)))
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+        // Boxed errors are not Send, so only the message leaves the pool
+        let windows: Vec<Window> = batch.into_par_iter()
+            .map(|(start, samples)| recognize_window(engine, start, &samples, sample_rate, options).map_err(|e| e.to_string()))
+            .collect::<Result<_, String>>()?;
+        for window in windows {
+            builder.push(window)?;
+        }
+    }
+
+    let mut runs = builder.finish()?;
+    settle_boundaries(&mut runs, duration, options);
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut covered = 0.0;
+    // A run squeezed out by its neighbours' boundaries is dropped
+    for run in runs.into_iter().filter(|run| run.end > run.start) {
+        if run.start > covered {
+            segments.push(Segment::unknown(covered, run.start));
+        }
+        let candidate = run.first.candidate.as_ref().ok_or("Segment without a recognized window")?;
+        segments.push(Segment {
+            start: run.start,
+            end: run.end,
+            song_id: Some(candidate.song_id),
+            offset: run.first.song_time(run.start),
+            tempo_factor: Some(candidate.tempo_factor),
+            confidence: Some(run.confidence),
+            windows: run.windows,
+        });
+        covered = run.end;
+    }
+    if duration > covered {
+        segments.push(Segment::unknown(covered, duration));
+    }
+
+    Ok(segments)
+}
@@ -0,0 +1,537 @@
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use crate::fingerprint::SchemeParameters;
+use crate::fingerprint_store::*;
+
+// Mirrors the MySQL schema in `database_interaction`: the same tables, the covering
+// lookup index and the cascading foreign key from fingerprints to songs.
+const MIGRATIONS: &[(u32, &str, &[&str])] = &[
+    (1, "create songs and fingerprints tables", &[
+        r"CREATE TABLE IF NOT EXISTS songs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            artist TEXT NOT NULL,
+            UNIQUE (name, artist)
+        )",
+        r"CREATE TABLE IF NOT EXISTS fingerprints (
+            hash_key INTEGER NOT NULL,
+            song_id INTEGER NOT NULL REFERENCES songs (id) ON DELETE CASCADE,
+            anchor_time REAL
+        )",
+        r"CREATE INDEX IF NOT EXISTS idx_fingerprints_lookup ON fingerprints (hash_key, song_id, anchor_time)",
+        r"CREATE INDEX IF NOT EXISTS idx_fingerprints_song ON fingerprints (song_id)",
+    ]),
+    // SQLite cannot drop a table constraint, so the songs table is rebuilt without the
+    // (name, artist) unique key. Runs with foreign keys disabled, see `SqliteStore::open`.
+    (2, "add optional song metadata and allow several versions of a title", &[
+        r"CREATE TABLE songs_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            artist TEXT NOT NULL,
+            album TEXT,
+            duration REAL,
+            isrc TEXT UNIQUE,
+            external_ids TEXT,
+            tags TEXT
+        )",
+        r"INSERT INTO songs_new (id, name, artist) SELECT id, name, artist FROM songs",
+        r"DROP TABLE songs",
+        r"ALTER TABLE songs_new RENAME TO songs",
+        r"CREATE INDEX idx_songs_name_artist ON songs (name, artist)",
+    ]),
+    (3, "track how many songs carry each hash", &[
+        r"CREATE TABLE IF NOT EXISTS hash_frequencies (
+            hash_key INTEGER NOT NULL PRIMARY KEY,
+            song_count INTEGER NOT NULL
+        )",
+        r"INSERT INTO hash_frequencies (hash_key, song_count)
+            SELECT hash_key, COUNT(DISTINCT song_id) FROM fingerprints GROUP BY hash_key",
+    ]),
+    (4, "store the content hash of ingested audio in its own column", &[
+        r"ALTER TABLE songs ADD COLUMN content_sha256 TEXT",
+        r"CREATE INDEX idx_songs_content_sha256 ON songs (content_sha256)",
+        // Batch ingests used to keep it under external_ids
+        r"UPDATE songs
+            SET content_sha256 = json_extract(external_ids, '$.content_sha256'),
+                external_ids = json_remove(external_ids, '$.content_sha256')
+            WHERE json_extract(external_ids, '$.content_sha256') IS NOT NULL",
+        r"UPDATE songs SET external_ids = NULL WHERE external_ids = '{}'",
+    ]),
+    (5, "record the fingerprint scheme of the catalogue", &[
+        r"CREATE TABLE IF NOT EXISTS store_metadata (
+            name TEXT NOT NULL PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        // Fingerprints stored until now were all made by scheme version 1 with pairs
+        r#"INSERT INTO store_metadata (name, value)
+            SELECT 'fingerprint_scheme', '{"version":1,"hashing":"pairs","sample_rate":8000.0,"frame_length":1024,"hop_length":512,"target_zone_frames":6,"max_targets_per_anchor":5}' WHERE EXISTS (SELECT 1 FROM fingerprints)"#,
+    ]),
+    (6, "remember the hashes the stop-list kept out of each song's fingerprints", &[
+        r"CREATE TABLE IF NOT EXISTS stopped_hashes (
+            song_id INTEGER NOT NULL REFERENCES songs (id) ON DELETE CASCADE,
+            hash_key INTEGER NOT NULL,
+            PRIMARY KEY (song_id, hash_key)
+        )",
+    ]),
+];
+
+const SONG_COLUMNS: &str = "id, name, artist, album, duration, isrc, external_ids, tags, content_sha256";
+
+fn song_from_row(row: &rusqlite::Row) -> rusqlite::Result<(i64, SongMetadata, Option<String>)> {
+    let metadata = SongMetadata {
+        name: row.get(1)?,
+        artist: row.get(2)?,
+        album: row.get(3)?,
+        duration: row.get(4)?,
+        isrc: row.get(5)?,
+        external_ids: None,
+        tags: row.get(7)?,
+        content_sha256: row.get(8)?,
+    };
+    Ok((row.get(0)?, metadata, row.get(6)?))
+}
+
+// external_ids is stored as JSON text and parsed outside the rusqlite row callback
+fn into_song_info((id, mut metadata, external_ids): (i64, SongMetadata, Option<String>)) -> Result<SongInfo, Box<dyn std::error::Error>> {
+    if let Some(json) = external_ids {
+        metadata.external_ids = Some(serde_json::from_str(&json)?);
+    }
+    Ok(SongInfo { id: id as u64, metadata })
+}
+
+// Number of hash keys bound per lookup statement, below SQLite's variable limit
+const LOOKUP_CHUNK_SIZE: usize = 500;
+
+// Rows per multi-row insert: up to three variables each, below the 999-variable limit of
+// SQLite builds before 3.32
+const INSERT_CHUNK_SIZE: usize = 300;
+
+// How long a write waits for another connection's write lock before failing as busy
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Fingerprint store in a single local SQLite file, for machines without a MySQL server
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = if path == ":memory:" {
+            Connection::open_in_memory()?
+        } else {
+            Connection::open(path)?
+        };
+
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+        // Migrations that rebuild a table must not cascade deletes through the foreign keys,
+        // which are off by default and only enabled per connection once the schema is current
+        let mut conn = conn;
+        conn.pragma_update(None, "foreign_keys", false)?;
+        run_migrations(&mut conn)?;
+        let violations: i64 = conn.query_row(r"SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+        if violations > 0 {
+            return Err(format!("Database has {} fingerprints without a song", violations).into());
+        }
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, Box<dyn std::error::Error>> {
+        self.conn.lock().map_err(|_| "SQLite store lock poisoned".into())
+    }
+}
+
+pub fn run_migrations(conn: &mut Connection) -> Result<u32, Box<dyn std::error::Error>> {
+    conn.execute(
+        r"CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER NOT NULL PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // SQLite DDL is transactional, so each migration applies completely or not at all
+    let tx = conn.transaction()?;
+    let current: Option<u32> = tx.query_row(r"SELECT MAX(version) FROM schema_migrations", [], |row| row.get(0))?;
+    let mut version = current.unwrap_or(0);
+
+    for &(migration_version, description, statements) in MIGRATIONS {
+        if migration_version <= version {
+            continue;
+        }
+
+        log::info!("Applying schema migration {}: {}", migration_version, description);
+        for statement in statements {
+            tx.execute(statement, [])
+                .map_err(|e| format!("Schema migration {} failed: {}", migration_version, e))?;
+        }
+        tx.execute(
+            r"INSERT INTO schema_migrations (version, description) VALUES (?1, ?2)",
+            params![migration_version, description],
+        )?;
+        version = migration_version;
+    }
+
+    tx.commit()?;
+    Ok(version)
+}
+
+fn insert_song(tx: &rusqlite::Transaction, metadata: &SongMetadata) -> Result<u64, Box<dyn std::error::Error>> {
+    let existing: Option<i64> = tx.query_row(
+        r"SELECT id FROM songs
+          WHERE (name = ?1 AND artist = ?2 AND album IS ?3 AND isrc IS ?4) OR isrc = ?4
+          LIMIT 1",
+        params![metadata.name, metadata.artist, metadata.album, metadata.isrc],
+        |row| row.get(0),
+    ).optional()?;
+    if existing.is_some() {
+        return Err(metadata.duplicate_error());
+    }
+
+    let external_ids = match &metadata.external_ids {
+        Some(value) => Some(serde_json::to_string(value)?),
+        None => None,
+    };
+    tx.execute(
+        r"INSERT INTO songs (name, artist, album, duration, isrc, external_ids, tags, content_sha256) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![metadata.name, metadata.artist, metadata.album, metadata.duration, metadata.isrc, external_ids, metadata.tags, metadata.content_sha256],
+    )?;
+
+    Ok(tx.last_insert_rowid() as u64)
+}
+
+// Hashes the song carries, stored or stopped
+fn carried_hashes(tx: &rusqlite::Transaction, song_id: u64) -> Result<HashSet<u64>, Box<dyn std::error::Error>> {
+    let mut statement = tx.prepare_cached(
+        r"SELECT hash_key FROM fingerprints WHERE song_id = ?1
+          UNION SELECT hash_key FROM stopped_hashes WHERE song_id = ?1",
+    )?;
+    let rows = statement.query_map(params![song_id as i64], |row| Ok(row.get::<_, i64>(0)? as u64))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn insert_fingerprint(tx: &rusqlite::Transaction, keys: &[u64], values: &[f64], song_id: u64) -> Result<usize, Box<dyn std::error::Error>> {
+    if keys.len() != values.len() {
+        return Err("Keys and values vectors must have the same length".into());
+    }
+
+    // Hashes the song already carried are counted for it already
+    let carried = carried_hashes(tx, song_id)?;
+    let new_keys: Vec<u64> = keys.iter().copied().filter(|key| !carried.contains(key)).collect();
+
+    // SQLite integers are signed; hashes are stored bit-for-bit as i64
+    for (key_chunk, value_chunk) in keys.chunks(INSERT_CHUNK_SIZE).zip(values.chunks(INSERT_CHUNK_SIZE)) {
+        let placeholders = vec!["(?, ?, ?)"; key_chunk.len()].join(", ");
+        let query = format!("INSERT INTO fingerprints (hash_key, song_id, anchor_time) VALUES {}", placeholders);
+
+        let mut parameters: Vec<rusqlite::types::Value> = Vec::with_capacity(key_chunk.len() * 3);
+        for (&key, &value) in key_chunk.iter().zip(value_chunk) {
+            parameters.extend([(key as i64).into(), (song_id as i64).into(), value.into()]);
+        }
+        tx.prepare_cached(&query)?.execute(params_from_iter(parameters))?;
+    }
+
+    count_songs_carrying(tx, &new_keys)?;
+    Ok(keys.len())
+}
+
+fn insert_stopped(tx: &rusqlite::Transaction, keys: &[u64], song_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let carried = carried_hashes(tx, song_id)?;
+    let new_keys: Vec<u64> = unique_sorted(keys).into_iter().filter(|key| !carried.contains(key)).collect();
+
+    for chunk in new_keys.chunks(INSERT_CHUNK_SIZE) {
+        let placeholders = vec!["(?, ?)"; chunk.len()].join(", ");
+        let query = format!("INSERT INTO stopped_hashes (song_id, hash_key) VALUES {}", placeholders);
+        let parameters = chunk.iter().flat_map(|&key| [song_id as i64, key as i64]);
+        tx.prepare_cached(&query)?.execute(params_from_iter(parameters))?;
+    }
+
+    count_songs_carrying(tx, &new_keys)
+}
+
+// Adds one song to the count of each of `keys`, which the song did not carry before, as
+// `database_interaction` does for MySQL
+fn count_songs_carrying(tx: &rusqlite::Transaction, keys: &[u64]) -> Result<(), Box<dyn std::error::Error>> {
+    for chunk in unique_sorted(keys).chunks(LOOKUP_CHUNK_SIZE) {
+        let placeholders = vec!["(?, 1)"; chunk.len()].join(", ");
+        let query = format!(
+            "INSERT INTO hash_frequencies (hash_key, song_count) VALUES {}
+             ON CONFLICT (hash_key) DO UPDATE SET song_count = song_count + 1",
+            placeholders,
+        );
+        tx.prepare_cached(&query)?.execute(params_from_iter(chunk.iter().map(|&key| key as i64)))?;
+    }
+    Ok(())
+}
+
+// Takes one song off the count of each of `keys`, dropping hashes no song carries any more
+fn uncount_songs_carrying(tx: &rusqlite::Transaction, keys: &[u64]) -> Result<(), Box<dyn std::error::Error>> {
+    for chunk in unique_sorted(keys).chunks(LOOKUP_CHUNK_SIZE) {
+        let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+        let chunk_params = || params_from_iter(chunk.iter().map(|&key| key as i64));
+        tx.prepare_cached(&format!("UPDATE hash_frequencies SET song_count = song_count - 1 WHERE hash_key IN ({})", placeholders))?
+            .execute(chunk_params())?;
+        tx.prepare_cached(&format!("DELETE FROM hash_frequencies WHERE hash_key IN ({}) AND song_count <= 0", placeholders))?
+            .execute(chunk_params())?;
+    }
+    Ok(())
+}
+
+fn unique_sorted(keys: &[u64]) -> Vec<u64> {
+    let mut unique_keys = keys.to_vec();
+    unique_keys.sort_unstable();
+    unique_keys.dedup();
+    unique_keys
+}
+
+// Another connection holding the write lock past `BUSY_TIMEOUT`; the transaction can be retried
+pub fn is_transient_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<rusqlite::Error>().and_then(rusqlite::Error::sqlite_error_code),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+impl FingerprintStore for SqliteStore {
+    fn register_song(&self, metadata: &SongMetadata) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+
+        let song_id = insert_song(&tx, metadata)?;
+        tx.commit()?;
+        Ok(song_id)
+    }
+
+    fn insert_hashes(&self, song_id: u64, keys: &[u64], values: &[f64]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+
+        let inserted = insert_fingerprint(&tx, keys, values, song_id)?;
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    fn insert_stopped_hashes(&self, song_id: u64, keys: &[u64]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+
+        insert_stopped(&tx, keys, song_id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn lookup_hashes(&self, keys: &[u64]) -> Result<Vec<FingerprintRow>, Box<dyn std::error::Error>> {
+        let unique_keys = unique_sorted(keys);
+
+        let conn = self.connection()?;
+        let mut result: Vec<FingerprintRow> = Vec::new();
+
+        for chunk in unique_keys.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let query = format!("SELECT hash_key, song_id, anchor_time FROM fingerprints WHERE hash_key IN ({})", placeholders);
+
+            let mut statement = conn.prepare_cached(&query)?;
+            let rows = statement.query_map(params_from_iter(chunk.iter().map(|&key| key as i64)), |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64, row.get::<_, f64>(2)?))
+            })?;
+            for row in rows {
+                result.push(row?);
+            }
+        }
+
+        result.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        Ok(result)
+    }
+
+    fn song_info(&self, song_id: u64) -> Result<SongInfo, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let result = conn.query_row(
+            &format!("SELECT {} FROM songs WHERE id = ?1", SONG_COLUMNS),
+            params![song_id as i64],
+            song_from_row,
+        ).optional()?;
+
+        match result {
+            Some(row) => into_song_info(row),
+            None => Err("Song not found".into()),
+        }
+    }
+
+    fn delete_song(&self, song_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+
+        let keys: Vec<u64> = carried_hashes(&tx, song_id)?.into_iter().collect();
+
+        // Fingerprints and stopped hashes go with the song through ON DELETE CASCADE
+        let deleted = tx.execute(r"DELETE FROM songs WHERE id = ?1", params![song_id as i64])?;
+        if deleted == 0 {
+            return Err("Song not found".into());
+        }
+
+        uncount_songs_carrying(&tx, &keys)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn list_songs(&self) -> Result<Vec<SongInfo>, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let mut statement = conn.prepare_cached(&format!("SELECT {} FROM songs ORDER BY id", SONG_COLUMNS))?;
+        let rows = statement.query_map([], song_from_row)?;
+
+        let mut result: Vec<SongInfo> = Vec::new();
+        for row in rows {
+            result.push(into_song_info(row?)?);
+        }
+        Ok(result)
+    }
+
+    fn song_hashes(&self, song_id: u64) -> Result<(Vec<u64>, Vec<f64>), Box<dyn std::error::Error>> {
+        self.song_info(song_id)?;
+        let conn = self.connection()?;
+
+        let mut statement = conn.prepare_cached(
+            r"SELECT hash_key, anchor_time FROM fingerprints WHERE song_id = ?1 ORDER BY anchor_time, hash_key",
+        )?;
+        let rows = statement.query_map(params![song_id as i64], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, f64>(1)?)))?;
+
+        let (mut keys, mut values) = (Vec::new(), Vec::new());
+        for row in rows {
+            let (key, value) = row?;
+            keys.push(key);
+            values.push(value);
+        }
+        Ok((keys, values))
+    }
+
+    fn song_count(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+        let count: i64 = conn.query_row(r"SELECT COUNT(*) FROM songs", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    fn hash_song_counts(&self, keys: &[u64]) -> Result<HashMap<u64, u64>, Box<dyn std::error::Error>> {
+        let unique_keys = unique_sorted(keys);
+
+        let conn = self.connection()?;
+        let mut result: HashMap<u64, u64> = HashMap::new();
+
+        for chunk in unique_keys.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let query = format!("SELECT hash_key, song_count FROM hash_frequencies WHERE hash_key IN ({})", placeholders);
+
+            let mut statement = conn.prepare_cached(&query)?;
+            let rows = statement.query_map(params_from_iter(chunk.iter().map(|&key| key as i64)), |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })?;
+            for row in rows {
+                let (key, count) = row?;
+                result.insert(key, count);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn fingerprint_counts(&self) -> Result<Vec<(u64, u64)>, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let mut statement = conn.prepare_cached(
+            r"SELECT s.id, COUNT(f.song_id) FROM songs s
+              LEFT JOIN fingerprints f ON f.song_id = s.id
+              GROUP BY s.id ORDER BY s.id",
+        )?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    // Ranked by the per-hash song counts, as for MySQL. Hashes are stored as signed integers,
+    // so the negative ones, the upper half of the u64 range, sort last to break ties by u64.
+    fn most_frequent_hashes(&self, limit: usize) -> Result<Vec<HashFrequency>, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let top: Vec<(u64, u64)> = {
+            let mut statement = conn.prepare_cached(
+                r"SELECT hash_key, song_count FROM hash_frequencies
+                  ORDER BY song_count DESC, hash_key < 0, hash_key LIMIT ?1",
+            )?;
+            let rows = statement.query_map(params![limit as i64], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut occurrences: HashMap<u64, u64> = HashMap::new();
+        let keys: Vec<u64> = top.iter().map(|&(hash, _)| hash).collect();
+        for chunk in keys.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let query = format!("SELECT hash_key, COUNT(*) FROM fingerprints WHERE hash_key IN ({}) GROUP BY hash_key", placeholders);
+
+            let mut statement = conn.prepare_cached(&query)?;
+            let rows = statement.query_map(params_from_iter(chunk.iter().map(|&key| key as i64)), |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })?;
+            for row in rows {
+                let (key, count) = row?;
+                occurrences.insert(key, count);
+            }
+        }
+
+        Ok(top.into_iter()
+            .map(|(hash, songs)| HashFrequency { hash, occurrences: occurrences.get(&hash).copied().unwrap_or(0), songs })
+            .collect())
+    }
+
+    fn storage_size(&self) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let size: i64 = conn.query_row(
+            r"SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(Some(size as u64))
+    }
+
+    fn scheme_parameters(&self) -> Result<Option<SchemeParameters>, Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        let value: Option<String> = conn.query_row(
+            r"SELECT value FROM store_metadata WHERE name = 'fingerprint_scheme'",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        match value {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_scheme_parameters(&self, parameters: &SchemeParameters) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection()?;
+
+        conn.execute(
+            r"INSERT OR IGNORE INTO store_metadata (name, value) VALUES ('fingerprint_scheme', ?1)",
+            params![serde_json::to_string(parameters)?],
+        )?;
+        Ok(())
+    }
+
+    fn ingest_song_with_stopped(&self, metadata: &SongMetadata, keys: &[u64], values: &[f64], stopped_keys: &[u64]) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+
+        let song_id = insert_song(&tx, metadata)?;
+        insert_fingerprint(&tx, keys, values, song_id)?;
+        insert_stopped(&tx, stopped_keys, song_id)?;
+
+        tx.commit()?;
+        Ok(song_id)
+    }
+}
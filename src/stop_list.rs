@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::env;
+
+// Below this many songs every shared hash looks common, so the stop-list stays inactive
+const DEFAULT_MIN_CATALOGUE_SONGS: u64 = 20;
+
+// Drops hashes carried by more than a given share of the catalogue's songs. Such hashes
+// (silence, steady tones, common drum patterns) match almost everything and only add noise
+// to the offset histogram. Applied when a song is ingested, where stopped hashes are kept out of
+// the fingerprint rows but still counted for the song, and again when a clip is queried, since
+// a hash can become common after the songs carrying it were stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopList {
+    max_song_fraction: f64,
+    min_catalogue_songs: u64,
+}
+
+impl StopList {
+    // `max_song_percent`: hashes found in more than this percentage of songs are dropped
+    pub fn new(max_song_percent: f64) -> Self {
+        Self { max_song_fraction: max_song_percent / 100.0, min_catalogue_songs: DEFAULT_MIN_CATALOGUE_SONGS }
+    }
+
+    pub fn with_min_catalogue_songs(mut self, min_catalogue_songs: u64) -> Self {
+        self.min_catalogue_songs = min_catalogue_songs;
+        self
+    }
+
+    // Reads SHAZAM_STOP_LIST_PERCENT and, optionally, SHAZAM_STOP_LIST_MIN_SONGS.
+    // No stop-list when the percentage is unset.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let percent = match env::var("SHAZAM_STOP_LIST_PERCENT") {
+            Ok(value) => value.parse::<f64>().map_err(|e| format!("Invalid SHAZAM_STOP_LIST_PERCENT '{}': {}", value, e))?,
+            Err(_) => return Ok(None),
+        };
+        let mut stop_list = Self::new(percent);
+        if let Ok(value) = env::var("SHAZAM_STOP_LIST_MIN_SONGS") {
+            stop_list.min_catalogue_songs = value.parse().map_err(|e| format!("Invalid SHAZAM_STOP_LIST_MIN_SONGS '{}': {}", value, e))?;
+        }
+        Ok(Some(stop_list))
+    }
+
+    pub fn is_stopped(&self, hash_song_count: u64, catalogue_songs: u64) -> bool {
+        catalogue_songs >= self.min_catalogue_songs
+            && hash_song_count as f64 > self.max_song_fraction * catalogue_songs as f64
+    }
+
+    // Keeps the (hash, anchor_time) pairs whose hash is not stopped. `song_counts` holds the
+    // number of songs carrying each hash, as returned by `FingerprintStore::hash_song_counts`.
+    pub fn filter(&self, keys: &[u64], values: &[f64], song_counts: &HashMap<u64, u64>, catalogue_songs: u64) -> (Vec<u64>, Vec<f64>) {
+        let (kept_keys, kept_values, _) = self.split(keys, values, song_counts, catalogue_songs);
+        (kept_keys, kept_values)
+    }
+
+    // Like `filter`, also returning the distinct stopped hashes in ascending order
+    pub fn split(&self, keys: &[u64], values: &[f64], song_counts: &HashMap<u64, u64>, catalogue_songs: u64) -> (Vec<u64>, Vec<f64>, Vec<u64>) {
+        let mut kept_keys = Vec::with_capacity(keys.len());
+        let mut kept_values = Vec::with_capacity(values.len());
+        let mut stopped = Vec::new();
+        for (&key, &value) in keys.iter().zip(values) {
+            if self.is_stopped(song_counts.get(&key).copied().unwrap_or(0), catalogue_songs) {
+                stopped.push(key);
+            } else {
+                kept_keys.push(key);
+                kept_values.push(value);
+            }
+        }
+        stopped.sort_unstable();
+        stopped.dedup();
+        (kept_keys, kept_values, stopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_hashes_above_the_song_share_once_the_catalogue_is_large_enough() {
+        let stop_list = StopList::new(50.0).with_min_catalogue_songs(4);
+
+        assert!(stop_list.is_stopped(3, 4));
+        assert!(!stop_list.is_stopped(2, 4));
+        assert!(!stop_list.is_stopped(3, 3), "inactive below the minimum catalogue size");
+    }
+
+    #[test]
+    fn split_keeps_pairs_in_order_and_reports_each_stopped_hash_once() {
+        let stop_list = StopList::new(50.0).with_min_catalogue_songs(1);
+        let song_counts = HashMap::from([(1, 1), (2, 9), (3, 2)]);
+        let keys = [2, 1, 2, 3, 4];
+        let values = [0.0, 0.5, 1.0, 1.5, 2.0];
+
+        let (kept_keys, kept_values, stopped) = stop_list.split(&keys, &values, &song_counts, 10);
+
+        assert_eq!(kept_keys, vec![1, 3, 4]);
+        assert_eq!(kept_values, vec![0.5, 1.5, 2.0]);
+        assert_eq!(stopped, vec![2]);
+        assert_eq!(stop_list.filter(&keys, &values, &song_counts, 10), (kept_keys, kept_values));
+    }
+
+    // One test owns the stop-list variables so parallel tests never see them half set
+    #[test]
+    fn reads_the_stop_list_from_the_environment() {
+        unsafe {
+            env::remove_var("SHAZAM_STOP_LIST_PERCENT");
+            env::remove_var("SHAZAM_STOP_LIST_MIN_SONGS");
+        }
+        assert_eq!(StopList::from_env().unwrap(), None);
+
+        unsafe { env::set_var("SHAZAM_STOP_LIST_PERCENT", "25") };
+        assert_eq!(StopList::from_env().unwrap(), Some(StopList::new(25.0)));
+
+        unsafe { env::set_var("SHAZAM_STOP_LIST_MIN_SONGS", "100") };
+        assert_eq!(StopList::from_env().unwrap(), Some(StopList::new(25.0).with_min_catalogue_songs(100)));
+
+        unsafe { env::set_var("SHAZAM_STOP_LIST_MIN_SONGS", "many") };
+        assert!(StopList::from_env().is_err());
+
+        unsafe { env::set_var("SHAZAM_STOP_LIST_PERCENT", "a quarter") };
+        assert!(StopList::from_env().is_err());
+
+        unsafe {
+            env::remove_var("SHAZAM_STOP_LIST_PERCENT");
+            env::remove_var("SHAZAM_STOP_LIST_MIN_SONGS");
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Batch ingest of WAV files written to a temp directory, into the in-memory store
+mod common;
+
+use common::{chord_song, temp_path, tone_song, SAMPLE_RATE};
+use shazam::batch_ingest::{collect_wav_files, ingest_batch, scan_directory, FileReport, FileStatus};
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::Fingerprint;
+use shazam::fingerprint_store::SongMetadata;
+use shazam::memory_store::MemoryStore;
+use std::path::{Path, PathBuf};
+
+// Removes the directory and the WAV files in it when dropped
+struct TempDirectory(PathBuf);
+
+impl TempDirectory {
+    fn new(name: &str) -> Self {
+        let directory = temp_path(name);
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        Self(directory)
+    }
+}
+
+impl Drop for TempDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write_wav(path: &Path, samples: &[f64]) {
+    let spec = hound::WavSpec { channels: 1, sample_rate: SAMPLE_RATE as u32, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for &sample in samples {
+        writer.write_sample((sample * 32767.0) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn statuses(files: &[FileReport]) -> Vec<(String, &'static str)> {
+    files.iter()
+        .map(|file| {
+            let status = match file.status {
+                FileStatus::Ingested { .. } => "ingested",
+                FileStatus::Skipped { .. } => "skipped",
+                FileStatus::Failed { .. } => "failed",
+            };
+            (file.name.clone(), status)
+        })
+        .collect()
+}
+
+#[test]
+fn batches_skip_audio_content_already_indexed() {
+    let directory = TempDirectory::new("batch");
+    write_wav(&directory.0.join("Synthesizer - Chords.wav"), &chord_song(10.0, 3));
+    write_wav(&directory.0.join("Synthesizer - Tones.wav"), &tone_song(10.0, 1));
+    // The same audio under another name
+    std::fs::copy(directory.0.join("Synthesizer - Tones.wav"), directory.0.join("Synthesizer - Tones copy.wav")).unwrap();
+
+    let engine = ShazamEngine::new(Box::new(MemoryStore::new()));
+    let entries = scan_directory(&directory.0, None).unwrap();
+    let report = ingest_batch(&engine, &entries, &|_, _, _| {}).unwrap();
+    // Files go in path order, so the copy comes first and the original is the duplicate
+    assert_eq!(statuses(&report.files), vec![
+        ("Chords".to_string(), "ingested"),
+        ("Tones copy".to_string(), "ingested"),
+        ("Tones".to_string(), "skipped"),
+    ]);
+    assert_eq!((report.ingested, report.skipped, report.failed), (2, 1, 0));
+
+    // The content hash is kept with the song, not among its external ids
+    let songs = engine.store().list_songs().unwrap();
+    assert_eq!(songs.len(), 2);
+    for song in &songs {
+        let file = report.files.iter().find(|file| file.name == song.metadata.name).unwrap();
+        assert!(song.metadata.content_sha256.is_some());
+        assert_eq!(song.metadata.content_sha256, file.content_hash);
+        assert_eq!(song.metadata.external_ids, None);
+    }
+    assert_eq!(report.files[1].content_hash, report.files[2].content_hash);
+
+    // A second run over the same files adds nothing
+    let report = ingest_batch(&engine, &entries, &|_, _, _| {}).unwrap();
+    assert_eq!((report.ingested, report.skipped, report.failed), (0, 3, 0));
+    assert_eq!(engine.store().song_count().unwrap(), 2);
+}
+
+#[test]
+fn batches_fall_back_to_the_next_copy_when_the_first_fails() {
+    let directory = TempDirectory::new("batch-fallback");
+    write_wav(&directory.0.join("Synthesizer - Tones.wav"), &tone_song(10.0, 1));
+    std::fs::copy(directory.0.join("Synthesizer - Tones.wav"), directory.0.join("Synthesizer - Tones copy.wav")).unwrap();
+
+    // Another song already holds the first copy's title, so storing that copy fails
+    let engine = ShazamEngine::new(Box::new(MemoryStore::new()));
+    let other = Fingerprint::from_samples(&chord_song(10.0, 3), SAMPLE_RATE).unwrap();
+    engine.ingest_fingerprint(&SongMetadata::new("Tones copy", "Synthesizer"), &other).unwrap();
+
+    let entries = scan_directory(&directory.0, None).unwrap();
+    let report = ingest_batch(&engine, &entries, &|_, _, _| {}).unwrap();
+    assert_eq!(statuses(&report.files), vec![
+        ("Tones copy".to_string(), "failed"),
+        ("Tones".to_string(), "ingested"),
+    ]);
+    let song = engine.store().list_songs().unwrap().into_iter().find(|song| song.metadata.name == "Tones").unwrap();
+    assert_eq!(song.metadata.content_sha256, report.files[1].content_hash);
+}
+
+#[test]
+fn collects_wav_files_from_directories_and_keeps_named_files() {
+    let directory = TempDirectory::new("collect");
+    std::fs::create_dir_all(directory.0.join("nested")).unwrap();
+    for name in ["b.wav", "a.WAV", "notes.txt", "nested/c.wav", "other.flac"] {
+        std::fs::write(directory.0.join(name), b"").unwrap();
+    }
+
+    let files = collect_wav_files(&[directory.0.join("other.flac"), directory.0.clone()]).unwrap();
+    let expected: Vec<PathBuf> = ["other.flac", "a.WAV", "b.wav", "nested/c.wav"].iter().map(|name| directory.0.join(name)).collect();
+    assert_eq!(files, expected);
+}
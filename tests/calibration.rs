@@ -0,0 +1,39 @@
+// Fits calibrations to samples drawn from a known model
+use shazam::calibration::{Calibration, CalibrationSample};
+use shazam::degradation::SeededRng;
+
+fn samples_from(model: &Calibration, count: usize, seed: u64) -> Vec<CalibrationSample> {
+    let mut rng = SeededRng::new(seed);
+    (0..count)
+        .map(|_| {
+            let hash_fraction = rng.range(-5.0, 0.0).exp();
+            let margin = rng.range(0.0, 1.5).exp();
+            let correct = rng.next_f64() < model.probability(hash_fraction, margin);
+            CalibrationSample { hash_fraction, margin, correct }
+        })
+        .collect()
+}
+
+#[test]
+fn fit_recovers_the_model_behind_the_samples() {
+    let model = Calibration { intercept: -2.0, hash_fraction_weight: 0.5, margin_weight: 4.0 };
+    let fitted = Calibration::fit(&samples_from(&model, 20_000, 1)).unwrap();
+
+    for (fitted, expected) in [(fitted.intercept, model.intercept), (fitted.hash_fraction_weight, model.hash_fraction_weight), (fitted.margin_weight, model.margin_weight)] {
+        assert!((fitted - expected).abs() < 0.2, "fitted {:?}, expected {:?}", fitted, model);
+    }
+}
+
+#[test]
+fn fit_needs_right_and_wrong_answers() {
+    let samples = vec![CalibrationSample { hash_fraction: 0.5, margin: 3.0, correct: true }; 10];
+    assert!(Calibration::fit(&samples).is_err());
+}
+
+#[test]
+fn default_calibration_is_a_probability_rising_with_the_margin() {
+    let calibration = Calibration::default();
+    let probabilities: Vec<f64> = [1.0, 1.2, 1.5, 2.0, 3.0].iter().map(|&margin| calibration.probability(0.3, margin)).collect();
+    assert!(probabilities.iter().all(|p| (0.0..=1.0).contains(p)), "{:?}", probabilities);
+    assert!(probabilities.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", probabilities);
+}
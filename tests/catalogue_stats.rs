@@ -0,0 +1,47 @@
+// Catalogue statistics over in-memory stores, and the nearest-rank distribution behind them
+use shazam::catalogue_stats::{catalogue_stats, Distribution};
+use shazam::fingerprint::{FingerprintScheme, SchemeParameters, FINGERPRINT_SCHEME_VERSION};
+use shazam::fingerprint_store::{FingerprintStore, SongMetadata};
+use shazam::memory_store::MemoryStore;
+
+#[test]
+fn distribution_uses_nearest_rank_percentiles() {
+    let distribution = Distribution::from_values(vec![10, 3, 7, 1, 9, 2, 8, 4, 6, 5]);
+    assert_eq!(distribution, Distribution { min: 1, max: 10, mean: 5.5, median: 5, p90: 9, p99: 10 });
+
+    // The rank rounds up: the median of four values is the second
+    let distribution = Distribution::from_values(vec![40, 10, 30, 20]);
+    assert_eq!((distribution.median, distribution.p90, distribution.p99), (20, 40, 40));
+
+    let distribution = Distribution::from_values(vec![7]);
+    assert_eq!(distribution, Distribution { min: 7, max: 7, mean: 7.0, median: 7, p90: 7, p99: 7 });
+
+    assert_eq!(Distribution::from_values(Vec::new()), Distribution::default());
+}
+
+#[test]
+fn empty_catalogue_has_no_scheme_yet() {
+    let stats = catalogue_stats(&MemoryStore::new(), 5).unwrap();
+    assert_eq!((stats.song_count, stats.fingerprint_count), (0, 0));
+    assert_eq!(stats.fingerprints_per_song, Distribution::default());
+    assert!(stats.most_frequent_hashes.is_empty());
+    assert_eq!(stats.index_size_bytes, None);
+    assert_eq!((stats.scheme_version, stats.hashing_scheme), (None, None));
+}
+
+#[test]
+fn stats_report_the_catalogue_and_its_scheme() {
+    let store = MemoryStore::new();
+    store.record_scheme_parameters(&SchemeParameters::current(FingerprintScheme::Triplets)).unwrap();
+    store.ingest_song(&SongMetadata::new("First", "Artist"), &[1, 2, 3, 3], &[0.0, 0.5, 1.0, 1.5]).unwrap();
+    store.ingest_song(&SongMetadata::new("Second", "Artist"), &[3, 4], &[0.0, 0.5]).unwrap();
+    store.ingest_song(&SongMetadata::new("Silent", "Artist"), &[], &[]).unwrap();
+
+    let stats = catalogue_stats(&store, 2).unwrap();
+    assert_eq!((stats.song_count, stats.fingerprint_count), (3, 6));
+    assert_eq!(stats.fingerprints_per_song, Distribution { min: 0, max: 4, mean: 2.0, median: 2, p90: 4, p99: 4 });
+    let frequent: Vec<(u64, u64, u64)> = stats.most_frequent_hashes.iter().map(|hash| (hash.hash, hash.songs, hash.occurrences)).collect();
+    assert_eq!(frequent, vec![(3, 2, 3), (1, 1, 1)]);
+    assert_eq!(stats.scheme_version, Some(FINGERPRINT_SCHEME_VERSION));
+    assert_eq!(stats.hashing_scheme, Some(FingerprintScheme::Triplets));
+}
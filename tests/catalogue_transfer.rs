@@ -0,0 +1,100 @@
+// Moves catalogues between in-memory stores through export files in the temp directory
+mod common;
+
+use common::temp_path;
+use shazam::catalogue_transfer::{export_catalogue, import_catalogue, SchemeParameters};
+use shazam::fingerprint::FingerprintScheme;
+use shazam::fingerprint_store::{FingerprintStore, SongMetadata};
+use shazam::memory_store::MemoryStore;
+
+// Removes the export file when dropped
+struct TempExport(String);
+
+impl TempExport {
+    fn new(name: &str) -> Self {
+        Self(temp_path(name).to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for TempExport {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn source_store() -> MemoryStore {
+    let store = MemoryStore::new();
+    let detailed = SongMetadata {
+        album: Some("Album".to_string()),
+        duration: Some(184.5),
+        isrc: Some("GBAYE0601498".to_string()),
+        external_ids: Some(serde_json::json!({"spotify": "4uLU6hMCjMI75M1A2tKUQC"})),
+        tags: Some("live".to_string()),
+        ..SongMetadata::new("First", "Artist")
+    };
+    store.ingest_song(&detailed, &[1, 2, 3, u64::MAX], &[0.5, 1.0, 1.5, 2.0]).unwrap();
+    store.ingest_song(&SongMetadata::new("Second", "Artist"), &[2, 3, 4], &[0.25, 0.75, 1.25]).unwrap();
+    store.ingest_song(&SongMetadata::new("Silent", "Artist"), &[], &[]).unwrap();
+    store
+}
+
+#[test]
+fn gzipped_export_round_trips_between_stores() {
+    let export = TempExport::new("round-trip.jsonl.gz");
+    let source = source_store();
+    assert_eq!(export_catalogue(&source, &export.0).unwrap(), 3);
+
+    let target = MemoryStore::new();
+    target.ingest_song(&SongMetadata::new("Already here", "Someone"), &[9], &[0.0]).unwrap();
+    let report = import_catalogue(&target, &export.0).unwrap();
+    assert_eq!((report.imported, report.skipped), (3, 0));
+
+    for song in source.list_songs().unwrap() {
+        let new_id = report.id_map[&song.id];
+        assert_eq!(target.song_info(new_id).unwrap().metadata, song.metadata);
+        assert_eq!(target.song_hashes(new_id).unwrap(), source.song_hashes(song.id).unwrap());
+    }
+    assert_eq!(target.song_count().unwrap(), 4);
+
+    // Importing the same file again adds nothing
+    let report = import_catalogue(&target, &export.0).unwrap();
+    assert_eq!((report.imported, report.skipped), (0, 3));
+    assert_eq!(target.song_count().unwrap(), 4);
+}
+
+#[test]
+fn export_with_other_scheme_is_rejected() {
+    let export = TempExport::new("other-scheme.jsonl");
+    export_catalogue(&source_store(), &export.0).unwrap();
+
+    let contents = std::fs::read_to_string(&export.0).unwrap();
+    let (header, songs) = contents.split_once('\n').unwrap();
+    let mut header: serde_json::Value = serde_json::from_str(header).unwrap();
+    let current = SchemeParameters::current(FingerprintScheme::Pairs);
+    let other_scheme = SchemeParameters { hop_length: current.hop_length * 2, ..current };
+    header["scheme"] = serde_json::to_value(&other_scheme).unwrap();
+    std::fs::write(&export.0, format!("{}\n{}", header, songs)).unwrap();
+
+    let target = MemoryStore::new();
+    let error = import_catalogue(&target, &export.0).unwrap_err();
+    assert!(error.to_string().contains("scheme parameters differ"), "unexpected error: {}", error);
+    assert_eq!(target.song_count().unwrap(), 0);
+}
+
+#[test]
+fn export_of_another_hashing_scheme_is_rejected() {
+    let export = TempExport::new("triplets.jsonl.gz");
+    let source = source_store();
+    source.record_scheme_parameters(&SchemeParameters::current(FingerprintScheme::Triplets)).unwrap();
+    export_catalogue(&source, &export.0).unwrap();
+
+    // A store of pairs refuses the triplets, an empty one takes them and their scheme
+    let pairs = MemoryStore::new();
+    pairs.record_scheme_parameters(&SchemeParameters::current(FingerprintScheme::Pairs)).unwrap();
+    assert!(import_catalogue(&pairs, &export.0).is_err());
+    assert_eq!(pairs.song_count().unwrap(), 0);
+
+    let empty = MemoryStore::new();
+    assert_eq!(import_catalogue(&empty, &export.0).unwrap().imported, 3);
+    assert_eq!(empty.scheme_parameters().unwrap(), Some(SchemeParameters::current(FingerprintScheme::Triplets)));
+}
@@ -0,0 +1,102 @@
+// Synthetic "songs" for the integration tests. Everything is generated from a seed, so the
+// tests need no audio files, and the same seed always yields the same samples.
+#![allow(dead_code)]
+
+use shazam::degradation::SeededRng;
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+pub const SAMPLE_RATE: f64 = 8000.0;
+
+// Equal-tempered pitch `semitones` above A2 (110 Hz)
+fn pitch(semitones: u64) -> f64 {
+    110.0 * 2f64.powf(semitones as f64 / 12.0)
+}
+
+// Short fade in and out so notes do not click
+fn envelope(i: usize, length: usize) -> f64 {
+    let fade = (0.01 * SAMPLE_RATE) as usize;
+    (i.min(length - 1 - i) as f64 / fade as f64).min(1.0)
+}
+
+// A melody of pure tones with a quieter second harmonic, one note every 0.2-0.5 s
+pub fn tone_song(seconds: f64, seed: u64) -> Vec<f64> {
+    let mut rng = SeededRng::new(seed);
+    let mut samples = vec![0.0; (seconds * SAMPLE_RATE) as usize];
+
+    let mut position = 0;
+    while position < samples.len() {
+        let length = ((rng.range(0.2, 0.5) * SAMPLE_RATE) as usize).min(samples.len() - position);
+        let frequency = pitch(12 + rng.next_u64() % 36);
+        for i in 0..length {
+            let t = i as f64 / SAMPLE_RATE;
+            let tone = 0.5 * (2.0 * PI * frequency * t).sin() + 0.15 * (4.0 * PI * frequency * t).sin();
+            samples[position + i] = tone * envelope(i, length);
+        }
+        position += length;
+    }
+    samples
+}
+
+// Three-note chords, a new one every 0.4-0.8 s
+pub fn chord_song(seconds: f64, seed: u64) -> Vec<f64> {
+    let mut rng = SeededRng::new(seed);
+    let mut samples = vec![0.0; (seconds * SAMPLE_RATE) as usize];
+
+    let mut position = 0;
+    while position < samples.len() {
+        let length = ((rng.range(0.4, 0.8) * SAMPLE_RATE) as usize).min(samples.len() - position);
+        let root = 12 + rng.next_u64() % 24;
+        let third = root + 3 + rng.next_u64() % 2;
+        let frequencies = [pitch(root), pitch(third), pitch(root + 7)];
+        for i in 0..length {
+            let t = i as f64 / SAMPLE_RATE;
+            let chord: f64 = frequencies.iter().map(|frequency| 0.25 * (2.0 * PI * frequency * t).sin()).sum();
+            samples[position + i] = chord * envelope(i, length);
+        }
+        position += length;
+    }
+    samples
+}
+
+// Glides of 0.3-0.7 s, each a linear sweep over up to an octave
+pub fn chirp_song(seconds: f64, seed: u64) -> Vec<f64> {
+    let mut rng = SeededRng::new(seed);
+    let mut samples = vec![0.0; (seconds * SAMPLE_RATE) as usize];
+
+    let mut position = 0;
+    while position < samples.len() {
+        let length = ((rng.range(0.3, 0.7) * SAMPLE_RATE) as usize).min(samples.len() - position);
+        let start = rng.range(250.0, 2500.0);
+        let end = start * 2f64.powf(rng.range(-1.0, 1.0));
+        let duration = length as f64 / SAMPLE_RATE;
+        for i in 0..length {
+            let t = i as f64 / SAMPLE_RATE;
+            let phase = 2.0 * PI * (start * t + (end - start) * t * t / (2.0 * duration));
+            samples[position + i] = 0.6 * phase.sin() * envelope(i, length);
+        }
+        position += length;
+    }
+    samples
+}
+
+pub fn excerpt(samples: &[f64], start_seconds: f64, seconds: f64) -> Vec<f64> {
+    let start = (start_seconds * SAMPLE_RATE) as usize;
+    samples[start..start + (seconds * SAMPLE_RATE) as usize].to_vec()
+}
+
+// Linear interpolation from `SAMPLE_RATE` up to `sample_rate`
+pub fn upsample(samples: &[f64], sample_rate: f64) -> Vec<f64> {
+    let ratio = sample_rate / SAMPLE_RATE;
+    (0..((samples.len() - 1) as f64 * ratio) as usize).map(|i| {
+        let position = i as f64 / ratio;
+        let (index, fraction) = (position as usize, position.fract());
+        samples[index] * (1.0 - fraction) + samples[index + 1] * fraction
+    }).collect()
+}
+
+// A path in the system temp directory that no other test process uses; the caller removes
+// the file
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("shazam-test-{}-{}", std::process::id(), name))
+}
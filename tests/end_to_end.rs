@@ -0,0 +1,298 @@
+// Ingests synthesized songs into the in-memory store and recognizes excerpts of them, clean
+// and degraded. Needs no audio files, database or network.
+mod common;
+
+use common::{SAMPLE_RATE, chirp_song, chord_song, excerpt, tone_song, upsample};
+use shazam::degradation::{Degradation, degrade};
+use shazam::engine::{MatchCandidate, ShazamEngine};
+use shazam::fingerprint::Fingerprint;
+use shazam::fingerprint_store::SongMetadata;
+use shazam::stop_list::StopList;
+use std::sync::OnceLock;
+
+const SONG_SECONDS: f64 = 40.0;
+const CLIP_SECONDS: f64 = 8.0;
+// Offsets are measured in STFT frames (64 ms), and a clip rarely starts on a frame boundary
+const OFFSET_TOLERANCE: f64 = 0.1;
+
+struct Song {
+    id: u64,
+    samples: Vec<f64>,
+}
+
+struct Catalogue {
+    engine: ShazamEngine,
+    songs: Vec<Song>,
+    // Indexed alongside `songs` and checked on its own by `recognizes_glide_excerpts`
+    glides: Song,
+}
+
+// Built once and shared, fingerprinting every song is the slow part
+fn catalogue() -> &'static Catalogue {
+    static CATALOGUE: OnceLock<Catalogue> = OnceLock::new();
+    CATALOGUE.get_or_init(|| {
+        let engine = ShazamEngine::from_url("memory://").unwrap();
+        let ingest = |title: &str, samples: Vec<f64>| {
+            let fingerprint = Fingerprint::from_samples(&samples, SAMPLE_RATE).unwrap();
+            let id = engine.ingest_fingerprint(&SongMetadata::new(title, "Synthesizer"), &fingerprint).unwrap();
+            Song { id, samples }
+        };
+
+        let songs = vec![
+            ingest("Tones", tone_song(SONG_SECONDS, 1)),
+            ingest("More tones", tone_song(SONG_SECONDS, 2)),
+            ingest("Chords", chord_song(SONG_SECONDS, 3)),
+            ingest("More chords", chord_song(SONG_SECONDS, 4)),
+        ];
+        let glides = ingest("Glides", chirp_song(SONG_SECONDS, 5));
+        Catalogue { engine, songs, glides }
+    })
+}
+
+fn best_match(engine: &ShazamEngine, clip: &[f64], sample_rate: f64) -> MatchCandidate {
+    let fingerprint = Fingerprint::from_samples(clip, sample_rate).unwrap();
+    let candidates = engine.recognize_candidates(&fingerprint).unwrap();
+    candidates.into_iter().next().expect("no candidate for the clip")
+}
+
+fn assert_match(candidate: &MatchCandidate, song: &Song, start: f64, case: &str) {
+    assert_eq!(candidate.song_id, song.id, "{}: wrong song", case);
+    assert!((candidate.offset - start).abs() <= OFFSET_TOLERANCE,
+        "{}: offset {:.3} s, clip starts at {:.3} s", case, candidate.offset, start);
+}
+
+#[test]
+fn recognizes_clean_excerpts_at_different_offsets() {
+    let catalogue = catalogue();
+    for song in &catalogue.songs {
+        for start in [0.0, 5.3, 17.25, SONG_SECONDS - CLIP_SECONDS] {
+            let clip = excerpt(&song.samples, start, CLIP_SECONDS);
+            let candidate = best_match(&catalogue.engine, &clip, SAMPLE_RATE);
+            assert_match(&candidate, song, start, &format!("song {} at {} s", song.id, start));
+        }
+    }
+}
+
+#[test]
+fn recognizes_short_excerpts() {
+    let catalogue = catalogue();
+    for song in &catalogue.songs {
+        let clip = excerpt(&song.samples, 12.0, 3.0);
+        let candidate = best_match(&catalogue.engine, &clip, SAMPLE_RATE);
+        assert_match(&candidate, song, 12.0, &format!("3 s of song {}", song.id));
+    }
+}
+
+#[test]
+fn recognizes_excerpts_with_additive_noise() {
+    let catalogue = catalogue();
+    let noises = [
+        Degradation::WhiteNoise { snr_db: 20.0 },
+        Degradation::WhiteNoise { snr_db: 10.0 },
+        Degradation::WhiteNoise { snr_db: 5.0 },
+        Degradation::WhiteNoise { snr_db: 0.0 },
+        Degradation::PinkNoise { snr_db: 5.0 },
+        Degradation::PinkNoise { snr_db: 0.0 },
+        Degradation::Babble { snr_db: 5.0, voices: 4 },
+        Degradation::Babble { snr_db: 0.0, voices: 4 },
+    ];
+
+    for (seed, song) in catalogue.songs.iter().enumerate() {
+        for noise in &noises {
+            let steps = [Degradation::Excerpt { seconds: CLIP_SECONDS }, noise.clone()];
+            let clip = degrade(&song.samples, SAMPLE_RATE, &steps, seed as u64).unwrap();
+            let candidate = best_match(&catalogue.engine, &clip.samples, SAMPLE_RATE);
+            assert_match(&candidate, song, clip.start, &format!("song {} with {:?}", song.id, noise));
+        }
+    }
+}
+
+#[test]
+fn recognizes_excerpts_through_channel_distortions() {
+    let catalogue = catalogue();
+    let channels = [
+        ("phone speaker", Degradation::phone_speaker()),
+        ("clipping", vec![Degradation::Clip { drive_db: 20.0 }]),
+        ("reverb", vec![Degradation::Reverb { rt60: 0.8, wet: 0.5 }]),
+        ("bandlimiting", vec![Degradation::Bandlimit { cutoff_hz: 2500.0, bits: 4 }]),
+        ("quiet", vec![Degradation::Gain { db: -30.0 }]),
+        ("phone in a noisy room", [Degradation::phone_speaker(), vec![Degradation::Babble { snr_db: 10.0, voices: 3 }]].concat()),
+    ];
+
+    for (seed, song) in catalogue.songs.iter().enumerate() {
+        for (name, channel) in &channels {
+            let steps = [vec![Degradation::Excerpt { seconds: CLIP_SECONDS }], channel.clone()].concat();
+            let clip = degrade(&song.samples, SAMPLE_RATE, &steps, 100 + seed as u64).unwrap();
+            let candidate = best_match(&catalogue.engine, &clip.samples, SAMPLE_RATE);
+            assert_match(&candidate, song, clip.start, &format!("song {} through {}", song.id, name));
+        }
+    }
+}
+
+#[test]
+fn recognizes_excerpts_recorded_at_another_sample_rate() {
+    let catalogue = catalogue();
+    let song = &catalogue.songs[2];
+    // The fingerprint resamples the clip back to 8 kHz
+    let clip = upsample(&excerpt(&song.samples, 9.0, CLIP_SECONDS), 44_100.0);
+
+    let candidate = best_match(&catalogue.engine, &clip, 44_100.0);
+    assert_match(&candidate, song, 9.0, "44.1 kHz clip");
+}
+
+#[test]
+fn reports_the_clip_position_within_the_song() {
+    let catalogue = catalogue();
+    let song = &catalogue.songs[1];
+    for start in [3.0, 21.7] {
+        let clip = Fingerprint::from_samples(&excerpt(&song.samples, start, CLIP_SECONDS), SAMPLE_RATE).unwrap();
+        let recognition = catalogue.engine.recognize_match(&clip).unwrap();
+        assert_eq!(recognition.song.id, song.id);
+        assert!((recognition.start - start).abs() <= OFFSET_TOLERANCE, "start {:.3} s, expected {:.3} s", recognition.start, start);
+        assert!((recognition.end - (start + CLIP_SECONDS)).abs() <= OFFSET_TOLERANCE, "end {:.3} s, expected {:.3} s", recognition.end, start + CLIP_SECONDS);
+    }
+
+    // A recording that starts a second before the song does
+    let mut samples = vec![0.0; SAMPLE_RATE as usize];
+    samples.extend(excerpt(&song.samples, 0.0, CLIP_SECONDS));
+    let recognition = catalogue.engine.recognize_match(&Fingerprint::from_samples(&samples, SAMPLE_RATE).unwrap()).unwrap();
+    assert_eq!(recognition.song.id, song.id);
+    assert_eq!(recognition.start, 0.0);
+    assert!((recognition.end - CLIP_SECONDS).abs() <= OFFSET_TOLERANCE, "end {:.3} s", recognition.end);
+
+    // And one that runs two seconds past the song's end
+    let mut samples = excerpt(&song.samples, SONG_SECONDS - CLIP_SECONDS, CLIP_SECONDS);
+    samples.extend(vec![0.0; 2 * SAMPLE_RATE as usize]);
+    let recognition = catalogue.engine.recognize_match(&Fingerprint::from_samples(&samples, SAMPLE_RATE).unwrap()).unwrap();
+    assert_eq!(recognition.song.id, song.id);
+    assert!((recognition.start - (SONG_SECONDS - CLIP_SECONDS)).abs() <= OFFSET_TOLERANCE, "start {:.3} s", recognition.start);
+    assert_eq!(recognition.end, SONG_SECONDS);
+}
+
+#[test]
+fn refines_offsets_between_frames() {
+    let catalogue = catalogue();
+    for song in &catalogue.songs {
+        // Starts that fall at different points between frame boundaries
+        for start in (0..12).map(|step| 1.0 + step as f64 * 2.517) {
+            let clip = excerpt(&song.samples, start, CLIP_SECONDS);
+            let candidate = best_match(&catalogue.engine, &clip, SAMPLE_RATE);
+            assert_eq!(candidate.song_id, song.id);
+            assert!((candidate.offset - start).abs() <= 0.045, "song {}: offset {:.3} s, clip starts at {:.3} s", song.id, candidate.offset, start);
+            assert!(candidate.offset_spread < 0.1, "song {}: spread {:.3} s", song.id, candidate.offset_spread);
+        }
+    }
+}
+
+#[test]
+fn estimates_negative_offsets() {
+    let catalogue = catalogue();
+    for song in &catalogue.songs {
+        // The recording starts 1.154 s before the song does
+        let lead = 9234;
+        let mut samples = vec![0.0; lead];
+        samples.extend(excerpt(&song.samples, 0.0, CLIP_SECONDS));
+
+        let candidate = best_match(&catalogue.engine, &samples, SAMPLE_RATE);
+        let expected = -(lead as f64) / SAMPLE_RATE;
+        assert_eq!(candidate.song_id, song.id);
+        assert!((candidate.offset - expected).abs() <= 0.045, "song {}: offset {:.3} s, expected {:.3} s", song.id, candidate.offset, expected);
+    }
+}
+
+#[test]
+fn recognition_fails_on_an_empty_catalogue() {
+    let engine = ShazamEngine::from_url("memory://").unwrap();
+    let clip = Fingerprint::from_samples(&tone_song(CLIP_SECONDS, 1), SAMPLE_RATE).unwrap();
+    assert!(engine.recognize_match(&clip).is_err());
+}
+
+// Glides produce far more hashes than the other songs, which must not cost them their rank
+#[test]
+fn recognizes_glide_excerpts() {
+    let catalogue = catalogue();
+    let glides = &catalogue.glides;
+    for start in (0..14).map(|step| step as f64 * 2.5) {
+        let clip = excerpt(&glides.samples, start, 5.0);
+        let candidate = best_match(&catalogue.engine, &clip, SAMPLE_RATE);
+        assert_match(&candidate, glides, start, &format!("glides at {} s", start));
+    }
+}
+
+#[test]
+fn degradation_is_reproducible_from_the_seed() {
+    let song = &catalogue().songs[0];
+    let steps = [
+        Degradation::Excerpt { seconds: CLIP_SECONDS },
+        Degradation::Babble { snr_db: 5.0, voices: 2 },
+        Degradation::Reverb { rt60: 0.5, wet: 0.3 },
+    ];
+
+    let first = degrade(&song.samples, SAMPLE_RATE, &steps, 42).unwrap();
+    let second = degrade(&song.samples, SAMPLE_RATE, &steps, 42).unwrap();
+    let other = degrade(&song.samples, SAMPLE_RATE, &steps, 43).unwrap();
+    assert_eq!(first.start, second.start);
+    assert_eq!(first.samples, second.samples);
+    assert_ne!(first.samples, other.samples);
+}
+
+#[test]
+fn deleted_songs_are_no_longer_recognized() {
+    let engine = ShazamEngine::from_url("memory://").unwrap();
+    let (kept, deleted) = (tone_song(20.0, 11), chord_song(20.0, 12));
+    let kept_id = engine.ingest_fingerprint(&SongMetadata::new("Kept", "Synthesizer"), &Fingerprint::from_samples(&kept, SAMPLE_RATE).unwrap()).unwrap();
+    let deleted_id = engine.ingest_fingerprint(&SongMetadata::new("Deleted", "Synthesizer"), &Fingerprint::from_samples(&deleted, SAMPLE_RATE).unwrap()).unwrap();
+
+    let clip = Fingerprint::from_samples(&excerpt(&deleted, 4.0, CLIP_SECONDS), SAMPLE_RATE).unwrap();
+    assert_eq!(engine.recognize_candidates(&clip).unwrap()[0].song_id, deleted_id);
+
+    engine.store().delete_song(deleted_id).unwrap();
+    let candidates = engine.recognize_candidates(&clip).unwrap();
+    assert!(candidates.iter().all(|candidate| candidate.song_id == kept_id));
+    assert!(engine.store().song_info(deleted_id).is_err());
+}
+
+// Hashes the stop-list drops at ingest are left out of the stored pairs, yet still count the
+// song towards their frequency until it is deleted
+#[test]
+fn stop_listed_hashes_are_counted_but_not_stored() {
+    let engine = ShazamEngine::from_url("memory://").unwrap().with_stop_list(StopList::new(50.0).with_min_catalogue_songs(2));
+    let fingerprint = Fingerprint::from_samples(&tone_song(20.0, 13), SAMPLE_RATE).unwrap();
+    let mut keys = fingerprint.hashes.clone();
+    keys.sort_unstable();
+    keys.dedup();
+
+    // Too few songs for the stop-list on the first ingest, every hash shared on the second
+    let original = engine.ingest_fingerprint(&SongMetadata::new("Original", "Synthesizer"), &fingerprint).unwrap();
+    let copy = engine.ingest_fingerprint(&SongMetadata::new("Copy", "Synthesizer"), &fingerprint).unwrap();
+    let store = engine.store();
+    assert_eq!(store.song_hashes(original).unwrap().0.len(), fingerprint.hashes.len());
+    assert!(store.song_hashes(copy).unwrap().0.is_empty());
+    assert!(store.lookup_hashes(&keys).unwrap().iter().all(|row| row.1 == original));
+    assert!(store.hash_song_counts(&keys).unwrap().values().all(|&count| count == 2));
+
+    store.delete_song(copy).unwrap();
+    assert!(store.hash_song_counts(&keys).unwrap().values().all(|&count| count == 1));
+}
+
+
+// Confidence is a calibrated probability: near-certain for clean clips of catalogue songs,
+// and well short of that for songs the catalogue has never seen
+#[test]
+fn confidence_separates_known_and_unknown_songs() {
+    let catalogue = catalogue();
+    for song in &catalogue.songs {
+        let clip = excerpt(&song.samples, 10.0, CLIP_SECONDS);
+        let candidate = best_match(&catalogue.engine, &clip, SAMPLE_RATE);
+        assert!(candidate.margin > 1.0, "song {}: margin {:.2}", song.id, candidate.margin);
+        assert!((95.0..=100.0).contains(&candidate.confidence), "song {}: {:.1}% confidence", song.id, candidate.confidence);
+    }
+
+    let unknown = [tone_song(SONG_SECONDS, 11), chord_song(SONG_SECONDS, 12), chirp_song(SONG_SECONDS, 13)];
+    for (i, samples) in unknown.iter().enumerate() {
+        let fingerprint = Fingerprint::from_samples(&excerpt(samples, 20.0, CLIP_SECONDS), SAMPLE_RATE).unwrap();
+        if let Some(candidate) = catalogue.engine.recognize_candidates(&fingerprint).unwrap().first() {
+            assert!(candidate.confidence < 90.0, "unknown song {}: {:.1}% confidence", i, candidate.confidence);
+        }
+    }
+}
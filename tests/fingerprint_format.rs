@@ -0,0 +1,74 @@
+// Binary fingerprint decoding, and fingerprinting audio too short for the spectrogram
+mod common;
+
+use common::{tone_song, SAMPLE_RATE};
+use shazam::fingerprint::Fingerprint;
+
+// Offset of the hash count in a format version 2 header
+const COUNT_OFFSET: usize = 24;
+// Samples between STFT frames at the 8 kHz the pipeline resamples to, `SAMPLE_RATE` here
+const HOP_LENGTH: usize = 512;
+
+fn fingerprint() -> Fingerprint {
+    Fingerprint::from_samples(&tone_song(5.0, 1), SAMPLE_RATE).unwrap()
+}
+
+#[test]
+fn binary_fingerprint_round_trips() {
+    let fingerprint = fingerprint();
+    assert!(!fingerprint.hashes.is_empty());
+    let decoded = Fingerprint::from_bytes(&fingerprint.to_bytes().unwrap()).unwrap();
+
+    // Anchor times are stored as frames, which `create_pairs` times fall on exactly
+    assert_eq!(decoded.scheme_version, fingerprint.scheme_version);
+    assert_eq!(decoded.scheme, fingerprint.scheme);
+    assert_eq!(decoded.duration, fingerprint.duration);
+    let mut expected: Vec<(u64, f64)> = fingerprint.hashes.iter().copied().zip(fingerprint.anchor_times.iter().copied()).collect();
+    expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    let actual: Vec<(u64, f64)> = decoded.hashes.into_iter().zip(decoded.anchor_times).collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn truncated_binary_fingerprint_is_an_error() {
+    let bytes = fingerprint().to_bytes().unwrap();
+    for len in 0..bytes.len() {
+        assert!(Fingerprint::from_bytes(&bytes[..len]).is_err(), "fingerprint cut to {} bytes decoded", len);
+    }
+}
+
+#[test]
+fn format_version_1_asks_for_a_new_fingerprint() {
+    let mut bytes = fingerprint().to_bytes().unwrap();
+    bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+    let error = Fingerprint::from_bytes(&bytes).unwrap_err();
+    assert!(error.to_string().contains("re-fingerprint the audio with scheme"), "unexpected error: {}", error);
+}
+
+// Pairs are timed by the start of their anchor's frame, not shifted by the peak's bin
+#[test]
+fn anchor_times_fall_on_frame_starts() {
+    let fingerprint = fingerprint();
+    for &time in &fingerprint.anchor_times {
+        let samples = time * SAMPLE_RATE;
+        assert_eq!(samples.fract(), 0.0, "anchor time {} is not a whole sample", time);
+        assert_eq!(samples as usize % HOP_LENGTH, 0, "anchor time {} is not a frame start", time);
+    }
+}
+
+#[test]
+fn hash_count_beyond_the_data_is_an_error() {
+    let mut bytes = fingerprint().to_bytes().unwrap();
+    bytes[COUNT_OFFSET..COUNT_OFFSET + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Fingerprint::from_bytes(&bytes).is_err());
+}
+
+#[test]
+fn audio_shorter_than_one_frame_is_an_error() {
+    for len in [0, 1, 100, 512] {
+        let error = Fingerprint::from_samples(&vec![0.1; len], SAMPLE_RATE).unwrap_err();
+        assert!(error.to_string().contains("shorter than one frame"), "unexpected error for {} samples: {}", len, error);
+    }
+    // Padding to the next hop makes up a whole frame
+    assert!(Fingerprint::from_samples(&vec![0.1; 513], SAMPLE_RATE).is_ok());
+}
@@ -0,0 +1,113 @@
+// Golden fingerprints: the peaks and hashes of a few fixture signals are stored under
+// tests/fixtures/golden/scheme-<FINGERPRINT_SCHEME_VERSION>/ and must be reproduced exactly.
+// A change to resampling, `make_spectr`, `process_spectr`, `create_hash` or `create_triplets`
+// that alters them
+// makes every stored index incompatible, so it has to come with a scheme version bump.
+//
+// After bumping the version, record the new goldens with
+//     SHAZAM_BLESS_GOLDEN=1 cargo test --test golden_fingerprints
+// Blessing only writes files that do not exist yet; goldens of a released scheme are never
+// overwritten.
+mod common;
+
+use common::{SAMPLE_RATE, chirp_song, chord_song, tone_song, upsample};
+use serde::{Deserialize, Serialize};
+use shazam::fingerprint::{FINGERPRINT_SCHEME_VERSION, Fingerprint, FingerprintScheme, spectral_peaks};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+const FIXTURE_SECONDS: f64 = 6.0;
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Golden {
+    sample_rate: f64,
+    peaks: Vec<Vec<usize>>,
+    fingerprint: Fingerprint,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/golden")
+        .join(format!("scheme-{}", FINGERPRINT_SCHEME_VERSION))
+        .join(format!("{}.json", name))
+}
+
+fn compute(samples: &[f64], sample_rate: f64, scheme: FingerprintScheme) -> Golden {
+    Golden {
+        sample_rate,
+        peaks: spectral_peaks(samples, sample_rate).unwrap(),
+        fingerprint: Fingerprint::from_samples_using(samples, sample_rate, scheme).unwrap(),
+    }
+}
+
+// Which frames and hashes changed, for the failure message
+fn describe_difference(expected: &Golden, actual: &Golden) -> String {
+    let changed_frames = expected.peaks.iter().zip(&actual.peaks).filter(|(a, b)| a != b).count()
+        + expected.peaks.len().abs_diff(actual.peaks.len());
+    let expected_hashes: HashSet<u64> = expected.fingerprint.hashes.iter().copied().collect();
+    let actual_hashes: HashSet<u64> = actual.fingerprint.hashes.iter().copied().collect();
+    format!(
+        "{} of {} frames have different peaks, {} golden hashes are gone, {} hashes are new, anchor times {}",
+        changed_frames,
+        expected.peaks.len(),
+        expected_hashes.difference(&actual_hashes).count(),
+        actual_hashes.difference(&expected_hashes).count(),
+        if expected.fingerprint.anchor_times == actual.fingerprint.anchor_times { "match" } else { "differ" },
+    )
+}
+
+fn check_golden(name: &str, samples: &[f64], sample_rate: f64) {
+    check_golden_using(name, samples, sample_rate, FingerprintScheme::Pairs);
+}
+
+fn check_golden_using(name: &str, samples: &[f64], sample_rate: f64, scheme: FingerprintScheme) {
+    let path = golden_path(name);
+    let actual = compute(samples, sample_rate, scheme);
+    assert_eq!(actual.fingerprint.scheme_version, FINGERPRINT_SCHEME_VERSION);
+
+    if !path.exists() {
+        assert!(std::env::var_os("SHAZAM_BLESS_GOLDEN").is_some(),
+            "No golden fingerprint at {}; run with SHAZAM_BLESS_GOLDEN=1 to record it", path.display());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, serde_json::to_string(&actual).unwrap()).unwrap();
+        return;
+    }
+
+    let expected: Golden = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(expected == actual,
+        "Fingerprint of '{}' differs from {}: {}. Bump FINGERPRINT_SCHEME_VERSION if the change is intended.",
+        name, path.display(), describe_difference(&expected, &actual));
+}
+
+#[test]
+fn tones_match_golden() {
+    check_golden("tones", &tone_song(FIXTURE_SECONDS, 1), SAMPLE_RATE);
+}
+
+#[test]
+fn chords_match_golden() {
+    check_golden("chords", &chord_song(FIXTURE_SECONDS, 3), SAMPLE_RATE);
+}
+
+#[test]
+fn glides_match_golden() {
+    check_golden("glides", &chirp_song(FIXTURE_SECONDS, 5), SAMPLE_RATE);
+}
+
+// Goes through the resampler as well
+#[test]
+fn tones_at_44_1_khz_match_golden() {
+    let tones = upsample(&tone_song(FIXTURE_SECONDS, 7), 44_100.0);
+    check_golden("tones_44100", &tones, 44_100.0);
+}
+
+#[test]
+fn tone_triplets_match_golden() {
+    check_golden_using("tones_triplets", &tone_song(FIXTURE_SECONDS, 1), SAMPLE_RATE, FingerprintScheme::Triplets);
+}
+
+#[test]
+fn chord_triplets_match_golden() {
+    check_golden_using("chords_triplets", &chord_song(FIXTURE_SECONDS, 3), SAMPLE_RATE, FingerprintScheme::Triplets);
+}
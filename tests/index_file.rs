@@ -0,0 +1,112 @@
+// Builds index files in the temp directory and reads them back, intact and damaged
+mod common;
+
+use common::temp_path;
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::{FingerprintScheme, SchemeParameters};
+use shazam::fingerprint_store::{FingerprintStore, SongMetadata};
+use shazam::index_file::{IndexBuilder, IndexFile};
+
+// Removes the index file when dropped
+struct TempIndex(String);
+
+impl TempIndex {
+    fn new(name: &str) -> Self {
+        Self(temp_path(name).to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for TempIndex {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn build_index(path: &str) -> u64 {
+    let mut builder = IndexBuilder::new();
+    builder.add_song(3, &SongMetadata::new("First", "Artist"), &[1, 2, 3, u64::MAX], &[0.5, 1.0, 1.5, 2.0]).unwrap();
+    builder.add_song(900, &SongMetadata::new("Second", "Artist"), &[2, 3, 4, 2], &[0.25, 0.75, 1.25, 1.75]).unwrap();
+    builder.write(path).unwrap()
+}
+
+#[test]
+fn built_index_opens_and_answers_lookups() {
+    let index = TempIndex::new("round-trip.idx");
+    let size = build_index(&index.0);
+    let store = IndexFile::open(&index.0).unwrap();
+
+    assert_eq!(store.storage_size().unwrap(), Some(size));
+    assert_eq!(store.song_count().unwrap(), 2);
+    assert_eq!(store.song_info(900).unwrap().metadata.name, "Second");
+    assert!(store.song_info(4).is_err());
+
+    let rows = store.lookup_hashes(&[3, 2, u64::MAX, 99]).unwrap();
+    assert_eq!(rows, vec![(2, 3, 1.0), (3, 3, 1.5), (u64::MAX, 3, 2.0), (2, 900, 0.25), (3, 900, 0.75), (2, 900, 1.75)]);
+    assert_eq!(store.song_hashes(900).unwrap(), (vec![2, 3, 4, 2], vec![0.25, 0.75, 1.25, 1.75]));
+    assert_eq!(store.hash_song_counts(&[1, 2, 4, 99]).unwrap(), [(1, 1), (2, 2), (4, 1)].into_iter().collect());
+    assert_eq!(store.fingerprint_counts().unwrap(), vec![(3, 4), (900, 4)]);
+    assert!(store.register_song(&SongMetadata::new("Third", "Artist")).is_err(), "index file accepted a write");
+}
+
+#[test]
+fn index_reads_every_song_in_one_pass() {
+    let index = TempIndex::new("all-songs.idx");
+    build_index(&index.0);
+    let store = IndexFile::open(&index.0).unwrap();
+
+    let songs: Vec<_> = store.all_songs_hashes().unwrap().collect::<Result<_, _>>().unwrap();
+    assert_eq!(songs.iter().map(|(song, _, _)| song.id).collect::<Vec<_>>(), vec![3, 900]);
+    for (song, keys, values) in &songs {
+        assert_eq!((keys.clone(), values.clone()), store.song_hashes(song.id).unwrap());
+    }
+
+    // Counts come from the hash table, most songs first, then lowest hash
+    let frequent = store.most_frequent_hashes(3).unwrap();
+    let frequent: Vec<(u64, u64, u64)> = frequent.iter().map(|hash| (hash.hash, hash.songs, hash.occurrences)).collect();
+    assert_eq!(frequent, vec![(2, 2, 3), (3, 2, 2), (1, 1, 1)]);
+
+    // Rebuilding from the index gives the same file
+    let rebuilt = TempIndex::new("all-songs-rebuilt.idx");
+    IndexBuilder::from_store(&store).unwrap().write(&rebuilt.0).unwrap();
+    assert_eq!(std::fs::read(&rebuilt.0).unwrap(), std::fs::read(&index.0).unwrap());
+}
+
+#[test]
+fn truncated_index_is_an_error() {
+    let index = TempIndex::new("truncated.idx");
+    build_index(&index.0);
+    let bytes = std::fs::read(&index.0).unwrap();
+
+    // Cutting the file anywhere fails to open it or to read the lost postings, without panicking
+    let damaged = TempIndex::new("truncated-copy.idx");
+    for len in 0..bytes.len() {
+        std::fs::write(&damaged.0, &bytes[..len]).unwrap();
+        let Ok(store) = IndexFile::open(&damaged.0) else {
+            continue;
+        };
+        assert!(store.lookup_hashes(&[1, 2, 3, 4, u64::MAX]).is_err(), "index cut to {} bytes answered every lookup", len);
+    }
+
+    // A posting count far beyond the file is rejected rather than allocated
+    let mut corrupt = bytes.clone();
+    let table_offset = u64::from_le_bytes(corrupt[56..64].try_into().unwrap()) as usize;
+    corrupt[table_offset + 16..table_offset + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&damaged.0, &corrupt).unwrap();
+    assert!(IndexFile::open(&damaged.0).unwrap().lookup_hashes(&[1]).is_err());
+}
+
+#[test]
+fn index_records_its_hashing_scheme() {
+    let index = TempIndex::new("triplets.idx");
+    let mut builder = IndexBuilder::new().with_scheme(FingerprintScheme::Triplets);
+    builder.add_song(1, &SongMetadata::new("First", "Artist"), &[1, 2], &[0.5, 1.0]).unwrap();
+    builder.write(&index.0).unwrap();
+
+    let store = IndexFile::open(&index.0).unwrap();
+    assert_eq!(store.scheme_parameters().unwrap(), Some(SchemeParameters::current(FingerprintScheme::Triplets)));
+
+    // An engine opened on the index hashes the way it was built
+    let engine = ShazamEngine::from_url(&format!("index://{}", index.0)).unwrap();
+    assert_eq!(engine.scheme(), FingerprintScheme::Triplets);
+    assert!(engine.with_scheme(FingerprintScheme::Pairs).check_store().is_err());
+}
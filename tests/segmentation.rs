@@ -0,0 +1,118 @@
+// Splits a recording of several synthesized songs, back to back, into a timeline
+mod common;
+
+use common::{SAMPLE_RATE, chirp_song, chord_song, excerpt, temp_path, tone_song};
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::Fingerprint;
+use shazam::fingerprint_store::SongMetadata;
+use shazam::segmentation::{SampleSource, Segment, SegmentationOptions, WavSource, segment_recording};
+
+const SONG_SECONDS: f64 = 40.0;
+// Boundaries fall on the first or last hash of a song, and notes last up to half a second
+const BOUNDARY_TOLERANCE: f64 = 0.5;
+const OFFSET_TOLERANCE: f64 = 0.1;
+
+// In-memory catalogue of three songs, ids and samples in ingest order
+fn catalogue() -> (ShazamEngine, Vec<(u64, Vec<f64>)>) {
+    let engine = ShazamEngine::from_url("memory://").unwrap();
+    let songs = [
+        ("Tones", tone_song(SONG_SECONDS, 1)),
+        ("Chords", chord_song(SONG_SECONDS, 3)),
+        ("Glides", chirp_song(SONG_SECONDS, 5)),
+    ]
+        .into_iter()
+        .map(|(title, samples)| {
+            let fingerprint = Fingerprint::from_samples(&samples, SAMPLE_RATE).unwrap();
+            let id = engine.ingest_fingerprint(&SongMetadata::new(title, "Synthesizer"), &fingerprint).unwrap();
+            (id, samples)
+        })
+        .collect();
+    (engine, songs)
+}
+
+// Asserts that `segment` plays `song_id` from recording time `start` to `end`, where the
+// song is at `song_start` seconds, or is unknown when `song_id` is None
+fn assert_segment(segment: &Segment, song_id: Option<u64>, start: f64, end: f64, song_start: f64) {
+    let case = format!("{:?}", segment);
+    assert_eq!(segment.song_id, song_id, "{}: wrong song", case);
+    assert!((segment.start - start).abs() <= BOUNDARY_TOLERANCE, "{}: should start at {} s", case, start);
+    assert!((segment.end - end).abs() <= BOUNDARY_TOLERANCE, "{}: should end at {} s", case, end);
+    if song_id.is_some() {
+        let offset = segment.offset.expect("no offset");
+        let expected = song_start + (segment.start - start);
+        assert!((offset - expected).abs() <= OFFSET_TOLERANCE, "{}: offset should be {:.3} s", case, expected);
+        assert!(segment.windows > 0, "{}: no windows", case);
+    }
+}
+
+#[test]
+fn splits_a_recording_into_songs_and_unknown_gaps() {
+    let (engine, songs) = catalogue();
+    let (tones, chords, glides) = (songs[0].0, songs[1].0, songs[2].0);
+    // 20 s of Tones from 8 s, 12 s of a song outside the catalogue, 25 s of Chords from 3 s,
+    // then Glides from the start and Tones again from the start
+    let recording: Vec<f64> = [
+        excerpt(&songs[0].1, 8.0, 20.0),
+        excerpt(&tone_song(SONG_SECONDS, 9), 5.0, 12.0),
+        excerpt(&songs[1].1, 3.0, 25.0),
+        excerpt(&songs[2].1, 0.0, 18.0),
+        excerpt(&songs[0].1, 0.0, 15.0),
+    ].concat();
+    let duration = recording.len() as f64 / SAMPLE_RATE;
+
+    let segments = segment_recording(&engine, &mut SampleSource::new(&recording, SAMPLE_RATE), &SegmentationOptions::default()).unwrap();
+    assert_eq!(segments.len(), 5, "{:#?}", segments);
+    assert_segment(&segments[0], Some(tones), 0.0, 20.0, 8.0);
+    assert_segment(&segments[1], None, 20.0, 32.0, 0.0);
+    assert_segment(&segments[2], Some(chords), 32.0, 57.0, 3.0);
+    assert_segment(&segments[3], Some(glides), 57.0, 75.0, 0.0);
+    assert_segment(&segments[4], Some(tones), 75.0, 90.0, 0.0);
+
+    // The timeline covers the recording without gaps or overlaps
+    assert_eq!(segments[0].start, 0.0);
+    assert!(segments.windows(2).all(|pair| pair[0].end == pair[1].start));
+    assert!((segments[4].end - duration).abs() < 1e-9);
+}
+
+#[test]
+fn a_recording_of_unknown_audio_is_one_unknown_segment() {
+    let (engine, _) = catalogue();
+    let recording = chord_song(30.0, 11);
+
+    let segments = segment_recording(&engine, &mut SampleSource::new(&recording, SAMPLE_RATE), &SegmentationOptions::default()).unwrap();
+    assert_eq!(segments.len(), 1, "{:#?}", segments);
+    assert_segment(&segments[0], None, 0.0, 30.0, 0.0);
+}
+
+#[test]
+fn rejects_windows_that_leave_gaps() {
+    let (engine, songs) = catalogue();
+    let options = SegmentationOptions { window: 5.0, hop: 10.0, ..SegmentationOptions::default() };
+    assert!(segment_recording(&engine, &mut SampleSource::new(&songs[0].1, SAMPLE_RATE), &options).is_err());
+}
+
+#[test]
+fn reads_stereo_wav_files_window_by_window() {
+    let (engine, songs) = catalogue();
+    let recording = [excerpt(&songs[1].1, 10.0, 20.0), excerpt(&songs[2].1, 5.0, 20.0)].concat();
+
+    // Both channels carry the recording, so the mono mix-down gives back its 16-bit samples
+    let path = temp_path("segment.wav");
+    let spec = hound::WavSpec { channels: 2, sample_rate: SAMPLE_RATE as u32, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    let mut quantized = Vec::with_capacity(recording.len());
+    for &sample in &recording {
+        let sample = (sample * 32767.0) as i16;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+        quantized.push(sample as f64 / 32768.0);
+    }
+    writer.finalize().unwrap();
+
+    let from_file = WavSource::open(path.to_str().unwrap())
+        .and_then(|mut source| segment_recording(&engine, &mut source, &SegmentationOptions::default()));
+    std::fs::remove_file(&path).unwrap();
+    let from_memory = segment_recording(&engine, &mut SampleSource::new(&quantized, SAMPLE_RATE), &SegmentationOptions::default()).unwrap();
+    assert_eq!(from_file.unwrap(), from_memory);
+    assert_eq!(from_memory.iter().filter_map(|segment| segment.song_id).collect::<Vec<_>>(), vec![songs[1].0, songs[2].0]);
+}
@@ -0,0 +1,103 @@
+// Segments recordings read window by window and checks that the memory used stays bounded by
+// the window length, not the recording's. On its own in this file, since it counts every
+// allocation the test binary makes.
+mod common;
+
+use common::{SAMPLE_RATE, chirp_song, chord_song, tone_song};
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::Fingerprint;
+use shazam::fingerprint_store::SongMetadata;
+use shazam::segmentation::{AudioSource, SegmentationOptions, segment_recording};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SONG_SECONDS: f64 = 40.0;
+
+// Bytes allocated now and the most allocated at once since the last reset
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = unsafe { System.alloc(layout) };
+        if !pointer.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(pointer, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The catalogue's songs played back to back over and over, synthesized as it is read, so the
+// recording itself takes no memory whatever its length
+struct Playlist<'a> {
+    songs: &'a [Vec<f64>],
+    seconds: f64,
+    // Longest stretch read at once, in samples
+    longest_read: usize,
+}
+
+impl AudioSource for Playlist<'_> {
+    fn sample_rate(&self) -> f64 {
+        SAMPLE_RATE
+    }
+
+    fn duration(&self) -> f64 {
+        self.seconds
+    }
+
+    fn read(&mut self, start: f64, end: f64) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+        let song_length = self.songs[0].len();
+        let (first, last) = ((start * SAMPLE_RATE) as usize, (end.min(self.seconds) * SAMPLE_RATE) as usize);
+        self.longest_read = self.longest_read.max(last - first);
+        Ok((first..last).map(|n| self.songs[(n / song_length) % self.songs.len()][n % song_length]).collect())
+    }
+}
+
+// Peak bytes allocated while segmenting `seconds` of the playlist, above what was allocated
+// before, and the number of segments found
+fn segment_playlist(engine: &ShazamEngine, songs: &[Vec<f64>], seconds: f64, options: &SegmentationOptions) -> (usize, usize) {
+    let mut playlist = Playlist { songs, seconds, longest_read: 0 };
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+
+    let segments = segment_recording(engine, &mut playlist, options).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    assert!(playlist.longest_read <= (options.window * SAMPLE_RATE) as usize, "read {} samples at once", playlist.longest_read);
+    (peak, segments.len())
+}
+
+#[test]
+fn memory_is_bounded_by_the_window_not_the_recording() {
+    let engine = ShazamEngine::from_url("memory://").unwrap();
+    let songs = vec![tone_song(SONG_SECONDS, 1), chord_song(SONG_SECONDS, 3), chirp_song(SONG_SECONDS, 5)];
+    for (index, samples) in songs.iter().enumerate() {
+        let fingerprint = Fingerprint::from_samples(samples, SAMPLE_RATE).unwrap();
+        engine.ingest_fingerprint(&SongMetadata::new(&format!("Song {}", index), "Synthesizer"), &fingerprint).unwrap();
+    }
+
+    // Windows back to back, on two threads so at most two windows are in flight
+    let options = SegmentationOptions { window: 10.0, hop: 10.0, ..SegmentationOptions::default() };
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    pool.install(|| {
+        // Warms up the pool and the lazily built FFT plans
+        segment_playlist(&engine, &songs, 20.0, &options);
+
+        let (short_peak, short_segments) = segment_playlist(&engine, &songs, 3.0 * SONG_SECONDS, &options);
+        let (long_peak, long_segments) = segment_playlist(&engine, &songs, 15.0 * SONG_SECONDS, &options);
+        assert_eq!((short_segments, long_segments), (3, 15));
+
+        // Five times the audio in about the same memory
+        assert!(long_peak < short_peak + short_peak / 4, "peak {} bytes for 10 min, {} bytes for 2 min", long_peak, short_peak);
+    });
+}
@@ -0,0 +1,153 @@
+// Exercises the SQLite store on temporary database files
+#![cfg(feature = "sqlite")]
+mod common;
+
+use common::temp_path;
+use rusqlite::Connection;
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::{Fingerprint, FingerprintScheme, SchemeParameters};
+use shazam::fingerprint_store::{FingerprintStore, SongMetadata};
+use shazam::sqlite_store::{run_migrations, SqliteStore};
+
+// Removes the database file and its WAL companions when dropped
+struct TempDatabase(String);
+
+impl TempDatabase {
+    fn new(name: &str) -> Self {
+        let database = Self(temp_path(name).to_string_lossy().into_owned());
+        database.remove();
+        database
+    }
+
+    fn remove(&self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.0, suffix));
+        }
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[test]
+fn ingests_looks_up_and_deletes_songs() {
+    let database = TempDatabase::new("ingest.db");
+    let store = SqliteStore::open(&database.0).unwrap();
+
+    let first = store.ingest_song(&SongMetadata::new("First", "Artist"), &[1, 2, 3, u64::MAX], &[0.5, 1.0, 1.5, 2.0]).unwrap();
+    let second = store.ingest_song(&SongMetadata::new("Second", "Artist"), &[2, 3, 4], &[0.25, 0.75, 1.25]).unwrap();
+    assert!(store.ingest_song(&SongMetadata::new("First", "Artist"), &[5], &[0.0]).is_err(), "duplicate song accepted");
+
+    // Grouped by song and ordered by anchor time; hashes above i64::MAX survive the round trip
+    let rows = store.lookup_hashes(&[3, 2, 2, u64::MAX, 99]).unwrap();
+    assert_eq!(rows, vec![(2, first, 1.0), (3, first, 1.5), (u64::MAX, first, 2.0), (2, second, 0.25), (3, second, 0.75)]);
+    assert_eq!(store.song_info(second).unwrap().metadata.name, "Second");
+    assert_eq!(store.song_hashes(first).unwrap(), (vec![1, 2, 3, u64::MAX], vec![0.5, 1.0, 1.5, 2.0]));
+    assert_eq!(store.hash_song_counts(&[1, 2, 4]).unwrap(), [(1, 1), (2, 2), (4, 1)].into_iter().collect());
+    // Ranked from the song counts, ties by unsigned hash even past i64::MAX
+    let frequent: Vec<(u64, u64, u64)> = store.most_frequent_hashes(5).unwrap().iter().map(|hash| (hash.hash, hash.songs, hash.occurrences)).collect();
+    assert_eq!(frequent, vec![(2, 2, 2), (3, 2, 2), (1, 1, 1), (4, 1, 1), (u64::MAX, 1, 1)]);
+
+    store.delete_song(first).unwrap();
+    assert!(store.song_info(first).is_err());
+    assert!(store.delete_song(first).is_err());
+    assert_eq!(store.lookup_hashes(&[1, 2, 3]).unwrap(), vec![(2, second, 0.25), (3, second, 0.75)]);
+    assert_eq!(store.hash_song_counts(&[1, 2]).unwrap(), [(2, 1)].into_iter().collect());
+    assert_eq!(store.song_count().unwrap(), 1);
+
+    // More hashes for a stored song count it once per hash
+    store.insert_hashes(second, &[2, 5, 5], &[2.0, 2.5, 3.0]).unwrap();
+    assert_eq!(store.hash_song_counts(&[2, 5]).unwrap(), [(2, 1), (5, 1)].into_iter().collect());
+}
+
+#[test]
+fn counts_stopped_hashes_without_storing_them() {
+    let database = TempDatabase::new("stopped.db");
+    let store = SqliteStore::open(&database.0).unwrap();
+
+    let first = store.ingest_song(&SongMetadata::new("First", "Artist"), &[1, 2], &[0.5, 1.0]).unwrap();
+    let second = store.ingest_song_with_stopped(&SongMetadata::new("Second", "Artist"), &[3], &[0.25], &[1, 2, 2]).unwrap();
+    assert_eq!(store.lookup_hashes(&[1, 2, 3]).unwrap(), vec![(1, first, 0.5), (2, first, 1.0), (3, second, 0.25)]);
+    assert_eq!(store.hash_song_counts(&[1, 2, 3]).unwrap(), [(1, 2), (2, 2), (3, 1)].into_iter().collect());
+
+    // A hash the song carries already, stored or stopped, is not counted twice
+    store.insert_hashes(second, &[2], &[2.0]).unwrap();
+    store.insert_stopped_hashes(second, &[3, 4]).unwrap();
+    assert_eq!(store.hash_song_counts(&[2, 3, 4]).unwrap(), [(2, 2), (3, 1), (4, 1)].into_iter().collect());
+
+    store.delete_song(second).unwrap();
+    assert_eq!(store.hash_song_counts(&[1, 2, 3, 4]).unwrap(), [(1, 1), (2, 1)].into_iter().collect());
+}
+
+#[test]
+fn ingests_songs_over_several_insert_chunks() {
+    let database = TempDatabase::new("chunks.db");
+    let store = SqliteStore::open(&database.0).unwrap();
+
+    // Enough rows for several multi-row inserts, with a partial chunk at the end
+    let keys: Vec<u64> = (0..1000).map(|i| i % 700).collect();
+    let values: Vec<f64> = (0..1000).map(|i| i as f64 * 0.064).collect();
+    let song_id = store.ingest_song(&SongMetadata::new("Long", "Artist"), &keys, &values).unwrap();
+
+    let (stored_keys, stored_values) = store.song_hashes(song_id).unwrap();
+    assert_eq!((stored_keys, stored_values), (keys, values));
+    let counts = store.hash_song_counts(&(0..700).collect::<Vec<_>>()).unwrap();
+    assert_eq!(counts.len(), 700);
+    assert!(counts.values().all(|&count| count == 1));
+
+    store.delete_song(song_id).unwrap();
+    assert!(store.hash_song_counts(&(0..700).collect::<Vec<_>>()).unwrap().is_empty());
+}
+
+#[test]
+fn migrations_run_once() {
+    let database = TempDatabase::new("migrations.db");
+    let song_id = {
+        let store = SqliteStore::open(&database.0).unwrap();
+        store.ingest_song(&SongMetadata::new("Kept", "Artist"), &[7, 8], &[0.0, 0.5]).unwrap()
+    };
+
+    // Reopening applies nothing and keeps the data
+    let store = SqliteStore::open(&database.0).unwrap();
+    assert_eq!(store.song_info(song_id).unwrap().metadata.name, "Kept");
+    assert_eq!(store.lookup_hashes(&[7, 8]).unwrap().len(), 2);
+
+    let mut conn = Connection::open(&database.0).unwrap();
+    let applied: Vec<u32> = conn.prepare("SELECT version FROM schema_migrations ORDER BY version").unwrap()
+        .query_map([], |row| row.get(0)).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let latest = *applied.last().unwrap();
+    assert_eq!(applied, (1..=latest).collect::<Vec<_>>());
+    assert_eq!(run_migrations(&mut conn).unwrap(), latest);
+    assert_eq!(run_migrations(&mut conn).unwrap(), latest);
+}
+
+#[test]
+fn store_keeps_the_scheme_of_its_fingerprints() {
+    let database = TempDatabase::new("scheme.db");
+    let url = format!("sqlite://{}", database.0);
+    let fingerprint = Fingerprint::from_samples_using(&common::tone_song(5.0, 1), common::SAMPLE_RATE, FingerprintScheme::Triplets).unwrap();
+    {
+        let engine = ShazamEngine::from_url(&url).unwrap().with_scheme(FingerprintScheme::Triplets);
+        assert_eq!(engine.store().scheme_parameters().unwrap(), None);
+        engine.ingest_fingerprint(&SongMetadata::new("Tones", "Synthesizer"), &fingerprint).unwrap();
+    }
+
+    let store = SqliteStore::open(&database.0).unwrap();
+    assert_eq!(store.scheme_parameters().unwrap(), Some(SchemeParameters::current(FingerprintScheme::Triplets)));
+    // The parameters recorded first stay
+    store.record_scheme_parameters(&SchemeParameters::current(FingerprintScheme::Pairs)).unwrap();
+    assert!(store.claim_scheme(&SchemeParameters::current(FingerprintScheme::Pairs)).is_err());
+
+    // Reopening hashes with the stored scheme, and a pairs engine is turned away
+    let engine = ShazamEngine::from_url(&url).unwrap();
+    assert_eq!(engine.scheme(), FingerprintScheme::Triplets);
+    assert!(engine.recognize_candidates(&fingerprint).is_ok());
+    let engine = engine.with_scheme(FingerprintScheme::Pairs);
+    assert!(engine.check_store().is_err());
+    let pairs = Fingerprint::from_samples(&common::tone_song(5.0, 1), common::SAMPLE_RATE).unwrap();
+    assert!(engine.ingest_fingerprint(&SongMetadata::new("Pairs", "Synthesizer"), &pairs).is_err());
+}
@@ -0,0 +1,116 @@
+// Recognizes sped-up and slowed-down excerpts: with the triplet fingerprint scheme, and with
+// the pair scheme for slight speed differences
+mod common;
+
+use common::{SAMPLE_RATE, chord_song, tone_song};
+use shazam::degradation::{Degradation, degrade};
+use shazam::engine::ShazamEngine;
+use shazam::fingerprint::{Fingerprint, FingerprintScheme};
+use shazam::fingerprint_store::SongMetadata;
+
+const SONG_SECONDS: f64 = 40.0;
+const CLIP_SECONDS: f64 = 8.0;
+const SPEEDS: [f64; 4] = [0.95, 0.97, 1.03, 1.05];
+// Two steps of the tempo grid
+const TEMPO_TOLERANCE: f64 = 0.01;
+const OFFSET_TOLERANCE: f64 = 0.2;
+// Speeds close enough to 1 for pair hashes, over clips long enough for the drift to show
+const LONG_CLIP_SECONDS: f64 = 30.0;
+const DRIFT_SPEEDS: [f64; 2] = [0.99, 1.01];
+const DRIFT_TEMPO_TOLERANCE: f64 = 0.003;
+// About a frame
+const DRIFT_OFFSET_TOLERANCE: f64 = 0.07;
+
+// In-memory catalogue fingerprinted with `scheme`, ids and samples in ingest order
+fn catalogue(scheme: FingerprintScheme) -> (ShazamEngine, Vec<(u64, Vec<f64>)>) {
+    let engine = ShazamEngine::from_url("memory://").unwrap().with_scheme(scheme);
+    let songs = [
+        ("Tones", tone_song(SONG_SECONDS, 1)),
+        ("More tones", tone_song(SONG_SECONDS, 2)),
+        ("Chords", chord_song(SONG_SECONDS, 3)),
+    ]
+        .into_iter()
+        .map(|(title, samples)| {
+            let fingerprint = Fingerprint::from_samples_using(&samples, SAMPLE_RATE, scheme).unwrap();
+            let id = engine.ingest_fingerprint(&SongMetadata::new(title, "Synthesizer"), &fingerprint).unwrap();
+            (id, samples)
+        })
+        .collect();
+    (engine, songs)
+}
+
+// Held chords leave few distinct onsets to fit the tempo to, so only the melodies are played
+// back at another speed; the chords compete for the matches
+#[test]
+fn triplets_recognize_clips_played_at_another_speed() {
+    let (engine, songs) = catalogue(FingerprintScheme::Triplets);
+    for (id, samples) in &songs[..2] {
+        for (seed, factor) in SPEEDS.into_iter().enumerate() {
+            let steps = [Degradation::Excerpt { seconds: CLIP_SECONDS }, Degradation::Speed { factor }];
+            let clip = degrade(samples, SAMPLE_RATE, &steps, seed as u64).unwrap();
+            let fingerprint = Fingerprint::from_samples_using(&clip.samples, SAMPLE_RATE, FingerprintScheme::Triplets).unwrap();
+            let candidates = engine.recognize_candidates(&fingerprint).unwrap();
+
+            let case = format!("song {} at x{}", id, factor);
+            let candidate = candidates.first().unwrap_or_else(|| panic!("{}: no candidate", case));
+            assert_eq!(candidate.song_id, *id, "{}: wrong song", case);
+            assert!((candidate.tempo_factor - factor).abs() <= TEMPO_TOLERANCE,
+                "{}: tempo factor {:.3}", case, candidate.tempo_factor);
+            assert!((candidate.offset - clip.start).abs() <= OFFSET_TOLERANCE,
+                "{}: offset {:.3} s, clip starts at {:.3} s", case, candidate.offset, clip.start);
+        }
+    }
+}
+
+// Unchanged clips still match at tempo 1
+#[test]
+fn triplets_recognize_clips_at_the_original_speed() {
+    let (engine, songs) = catalogue(FingerprintScheme::Triplets);
+    for (id, samples) in &songs {
+        let clip = degrade(samples, SAMPLE_RATE, &[Degradation::Excerpt { seconds: CLIP_SECONDS }], 7).unwrap();
+        let fingerprint = Fingerprint::from_samples_using(&clip.samples, SAMPLE_RATE, FingerprintScheme::Triplets).unwrap();
+        let candidate = engine.recognize_candidates(&fingerprint).unwrap().into_iter().next().expect("no candidate");
+        assert_eq!(candidate.song_id, *id);
+        assert!((candidate.tempo_factor - 1.0).abs() <= TEMPO_TOLERANCE, "tempo factor {:.3}", candidate.tempo_factor);
+    }
+}
+
+// Pair hashes survive a slight speed difference, but their offsets drift by a frame every few
+// seconds; over a long clip the line fit recovers both the tempo and the offset
+#[test]
+fn pairs_fit_slight_speed_drift_in_long_clips() {
+    let (engine, songs) = catalogue(FingerprintScheme::Pairs);
+    for (id, samples) in &songs {
+        for (seed, factor) in DRIFT_SPEEDS.into_iter().enumerate() {
+            let steps = [Degradation::Excerpt { seconds: LONG_CLIP_SECONDS }, Degradation::Speed { factor }];
+            let clip = degrade(samples, SAMPLE_RATE, &steps, seed as u64).unwrap();
+            let fingerprint = Fingerprint::from_samples(&clip.samples, SAMPLE_RATE).unwrap();
+            let candidates = engine.recognize_candidates(&fingerprint).unwrap();
+
+            let case = format!("song {} at x{}", id, factor);
+            let candidate = candidates.first().unwrap_or_else(|| panic!("{}: no candidate", case));
+            assert_eq!(candidate.song_id, *id, "{}: wrong song", case);
+            assert!((candidate.tempo_factor - factor).abs() <= DRIFT_TEMPO_TOLERANCE,
+                "{}: tempo factor {:.4}", case, candidate.tempo_factor);
+            assert!((candidate.offset - clip.start).abs() <= DRIFT_OFFSET_TOLERANCE,
+                "{}: offset {:.3} s, clip starts at {:.3} s", case, candidate.offset, clip.start);
+        }
+    }
+}
+
+#[test]
+fn engine_rejects_fingerprints_of_another_scheme() {
+    let (engine, songs) = catalogue(FingerprintScheme::Triplets);
+    let fingerprint = Fingerprint::from_samples(&songs[0].1[..(SAMPLE_RATE * CLIP_SECONDS) as usize], SAMPLE_RATE).unwrap();
+    assert!(engine.recognize_candidates(&fingerprint).is_err());
+    assert!(engine.ingest_fingerprint(&SongMetadata::new("Pairs", "Synthesizer"), &fingerprint).is_err());
+}
+
+#[test]
+fn scheme_survives_the_binary_format() {
+    let samples = tone_song(CLIP_SECONDS, 1);
+    let fingerprint = Fingerprint::from_samples_using(&samples, SAMPLE_RATE, FingerprintScheme::Triplets).unwrap();
+    let decoded = Fingerprint::from_bytes(&fingerprint.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded.scheme, FingerprintScheme::Triplets);
+    assert_eq!(decoded.hashes.len(), fingerprint.hashes.len());
+}